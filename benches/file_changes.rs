@@ -0,0 +1,104 @@
+use chrono::{Duration, TimeZone, Utc};
+use complexity_radar::history::{CommitChanges, FileChange, HistoryQuery};
+use complexity_radar::{count_file_changes, TopChangedFilesExt};
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+
+const NUM_COMMITS: usize = 100_000;
+const FILES_PER_COMMIT: usize = 5;
+
+/// A monorepo-like history where every commit changes a few of `num_files` files, some of them far more often.
+fn synthetic_history(num_files: usize) -> Vec<CommitChanges> {
+    let first_commit = Utc.with_ymd_and_hms(2020, 1, 1, 0, 0, 0).unwrap();
+    let mut seed = 42u64;
+    let mut next_random = move || {
+        // Linear congruential generator, so that every run benchmarks the same history
+        seed = seed
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        (seed >> 33) as usize
+    };
+    (0..NUM_COMMITS)
+        .rev()
+        .map(|commit| CommitChanges {
+            sha: format!("{commit:040x}"),
+            author: Some(format!("dev{}", next_random() % 50)),
+            date: Some(first_commit + Duration::minutes(commit as i64)),
+            message: "Change things".into(),
+            files: (0..FILES_PER_COMMIT)
+                .map(|_| {
+                    // Squaring a uniform value skews the changes towards the first files
+                    let random = next_random() % num_files;
+                    FileChange {
+                        filename: format!(
+                            "src/module{}/file{}.rs",
+                            random / 100,
+                            random * random / num_files
+                        ),
+                        additions: 3,
+                        deletions: 1,
+                        ..Default::default()
+                    }
+                })
+                .collect(),
+            num_parents: Some(1),
+        })
+        .collect()
+}
+
+fn query() -> HistoryQuery {
+    HistoryQuery {
+        since: Utc.with_ymd_and_hms(2019, 1, 1, 0, 0, 0).unwrap(),
+        ..HistoryQuery::default()
+    }
+}
+
+fn get_top_changed_files(c: &mut Criterion) {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let query = query();
+    let mut group = c.benchmark_group("get_top_changed_files");
+    group.sample_size(10);
+    for num_files in [1_000, 10_000, 100_000] {
+        let history = synthetic_history(num_files);
+        group.bench_with_input(
+            BenchmarkId::from_parameter(num_files),
+            &history,
+            |b, history| {
+                b.iter(|| {
+                    runtime
+                        .block_on(history.get_top_changed_files(10, &query))
+                        .unwrap()
+                })
+            },
+        );
+    }
+    group.finish();
+}
+
+/// The baseline: counting the changes, then sorting every changed file to keep the top ones.
+fn sort_all_changed_files(c: &mut Criterion) {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let query = query();
+    let mut group = c.benchmark_group("sort_all_changed_files");
+    group.sample_size(10);
+    for num_files in [1_000, 10_000, 100_000] {
+        let history = synthetic_history(num_files);
+        group.bench_with_input(
+            BenchmarkId::from_parameter(num_files),
+            &history,
+            |b, history| {
+                b.iter(|| {
+                    let mut changed_files = runtime
+                        .block_on(count_file_changes(history, &query))
+                        .unwrap();
+                    changed_files.sort_by_key(|stats| std::cmp::Reverse(stats.num_commits));
+                    changed_files.truncate(10);
+                    changed_files
+                })
+            },
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(benches, get_top_changed_files, sort_all_changed_files);
+criterion_main!(benches);
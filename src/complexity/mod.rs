@@ -1,7 +1,10 @@
-use anyhow::{anyhow, Result};
-use std::path::PathBuf;
+use anyhow::{anyhow, Context, Result};
+use std::io::Write;
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
 use std::process::Command;
 use std::{fs, vec};
+use syn::spanned::Spanned;
 use syn::{
     self, Block, Expr, ExprBlock, ExprClosure, ExprForLoop, ExprIf, ExprLet, ExprMatch,
     ExprMethodCall, ExprWhile, Item, ItemFn, Stmt,
@@ -13,6 +16,13 @@ pub struct FunctionComplexity {
     pub cognitive_complexity_value: u16,
 }
 
+/// Lines of its source file a function spans, from its attributes to its closing brace.
+#[derive(PartialEq, Eq, Debug)]
+pub struct FunctionSpan {
+    pub function: String,
+    pub lines: RangeInclusive<u32>,
+}
+
 pub fn compute_cognitive_index(
     prog_lang: ProgrammingLang,
     file: PathBuf,
@@ -21,9 +31,33 @@ pub fn compute_cognitive_index(
     lang_evaluator.eval(file)
 }
 
+/// Same as [`compute_cognitive_index`], but for code we already have in memory, like a file fetched from the
+/// forge at a specific ref. `file` is only used to tell the kind of source file.
+pub fn compute_cognitive_index_of_code(
+    prog_lang: ProgrammingLang,
+    file: &Path,
+    code: &str,
+) -> Result<Vec<FunctionComplexity>> {
+    let lang_evaluator = create_lang_evaluator(prog_lang);
+    lang_evaluator.eval_code(file, code)
+}
+
+/// Finds where every function of the code starts and ends, to tell which functions a change touched.
+pub fn find_function_spans(
+    prog_lang: ProgrammingLang,
+    file: &Path,
+    code: &str,
+) -> Result<Vec<FunctionSpan>> {
+    let lang_evaluator = create_lang_evaluator(prog_lang);
+    lang_evaluator.function_spans(file, code)
+}
+
 pub enum ProgrammingLang {
     Rust,
+    // Placeholders, no evaluator is wired in for them yet
+    #[allow(dead_code)]
     Python,
+    #[allow(dead_code)]
     Go,
 }
 
@@ -31,30 +65,75 @@ const NESTING_LEVEL_ZERO: u16 = 0;
 
 trait LangEvaluator {
     fn eval(&self, file: PathBuf) -> Result<Vec<FunctionComplexity>>;
+
+    fn eval_code(&self, file: &Path, code: &str) -> Result<Vec<FunctionComplexity>> {
+        // Evaluators relying on external tools need the code in a file, with the same extension as the original one
+        let extension = file
+            .extension()
+            .map(|extension| format!(".{}", extension.to_string_lossy()))
+            .unwrap_or_default();
+        let mut code_file = tempfile::Builder::new().suffix(&extension).tempfile()?;
+        code_file.write_all(code.as_bytes())?;
+        self.eval(code_file.path().into())
+    }
+
+    fn function_spans(&self, file: &Path, _code: &str) -> Result<Vec<FunctionSpan>> {
+        Err(anyhow!(
+            "Can't find the functions of {}",
+            file.to_string_lossy()
+        ))
+    }
 }
 struct RustLangEvaluator;
 impl LangEvaluator for RustLangEvaluator {
     fn eval(&self, file: PathBuf) -> Result<Vec<FunctionComplexity>> {
-        if let Some(extension) = file.extension() {
-            if extension != "rs" {
-                return Err(anyhow!("Invalid source file"));
-            }
-        }
+        check_rust_source_file(&file)?;
+
+        let code = fs::read_to_string(&file).with_context(|| {
+            format!(
+                "Cannot open code file: {}: Make sure you have cloned the repository locally",
+                file.to_string_lossy()
+            )
+        })?;
+        self.eval_code(&file, &code)
+    }
+
+    fn eval_code(&self, file: &Path, code: &str) -> Result<Vec<FunctionComplexity>> {
+        check_rust_source_file(file)?;
 
-        let code = fs::read_to_string(&file)
-            .map_err(|e| {
-                format!(
-                    "Cannot open code file: {}: Make sure you have cloned the repository locally. Error: {}",
-                    file.to_string_lossy().to_string(),
-                    e.to_string()
-                )
+        let syntax_tree = syn::parse_file(code)?;
+        calc_complexities_by_function(syntax_tree)
+    }
+
+    fn function_spans(&self, file: &Path, code: &str) -> Result<Vec<FunctionSpan>> {
+        check_rust_source_file(file)?;
+
+        let syntax_tree = syn::parse_file(code)?;
+        Ok(syntax_tree
+            .items
+            .iter()
+            .filter_map(|item| match item {
+                Item::Fn(item_fn) => Some(item_fn),
+                _ => None,
             })
-            .unwrap();
-        let syntax_tree = syn::parse_file(&code)?;
-        let functions_complexity = calc_complexities_by_function(syntax_tree);
+            .map(|func| {
+                let span = func.span();
+                FunctionSpan {
+                    function: get_function_name(func),
+                    lines: span.start().line as u32..=span.end().line as u32,
+                }
+            })
+            .collect())
+    }
+}
 
-        functions_complexity
+fn check_rust_source_file(file: &Path) -> Result<()> {
+    if let Some(extension) = file.extension() {
+        if extension != "rs" {
+            return Err(anyhow!("Invalid source file"));
+        }
     }
+    Ok(())
 }
 
 fn calc_complexities_by_function(syntax_tree: syn::File) -> Result<Vec<FunctionComplexity>> {
@@ -69,10 +148,10 @@ fn calc_complexities_by_function(syntax_tree: syn::File) -> Result<Vec<FunctionC
             }
         })
         .map(|func| {
-            let cognitive_complexity_value = cognitive_complexity_func(&func);
+            let cognitive_complexity_value = cognitive_complexity_func(func);
             FunctionComplexity {
-                function: get_function_name(&func),
-                cognitive_complexity_value: cognitive_complexity_value,
+                function: get_function_name(func),
+                cognitive_complexity_value,
             }
         })
         .collect::<Vec<FunctionComplexity>>())
@@ -87,7 +166,7 @@ fn cognitive_complexity_func(func: &ItemFn) -> u16 {
 }
 
 fn cognitive_complexity_block(block: &Block, nesting_level: u16) -> u16 {
-    let Block { stmts, .. } = &*block;
+    let Block { stmts, .. } = block;
     stmts
         .iter()
         .map(|stmt| match stmt {
@@ -95,7 +174,7 @@ fn cognitive_complexity_block(block: &Block, nesting_level: u16) -> u16 {
                 cognitive_complexity_expr(expr, nesting_level)
             }
             Stmt::Local(local) => match &local.init {
-                Some((_, expr)) => cognitive_complexity_expr(&expr, nesting_level),
+                Some((_, expr)) => cognitive_complexity_expr(expr, nesting_level),
                 None => 0,
             },
             _ => 0,
@@ -134,7 +213,7 @@ fn cognitive_complexity_expr(expr: &Expr, nesting_level: u16) -> u16 {
                 .iter()
                 .map(|argument| cognitive_complexity_expr(argument, nesting_level))
                 .sum();
-            complex_index_sum + cognitive_complexity_expr(&receiver, nesting_level)
+            complex_index_sum + cognitive_complexity_expr(receiver, nesting_level)
         }
         Expr::Closure(ExprClosure { body, .. }) => {
             // The closure (lambda) itself doesn't add to the index, but increments nesting level
@@ -154,8 +233,11 @@ fn cognitive_complexity_expr(expr: &Expr, nesting_level: u16) -> u16 {
     expr_cognitive_index + nesting_level
 }
 
+// Placeholder, not wired in yet
+#[allow(dead_code)]
 struct PythonLangEvaluator;
 impl LangEvaluator for PythonLangEvaluator {
+    #[allow(clippy::suspicious_command_arg_space, clippy::useless_format)]
     fn eval(&self, file: PathBuf) -> Result<Vec<FunctionComplexity>> {
         let file_path = file.into_os_string().into_string().unwrap();
         let output = Command::new("flake8")
@@ -178,7 +260,8 @@ impl LangEvaluator for PythonLangEvaluator {
     }
 }
 
-fn get_function_complexities_from_flake8(text: String) -> Result<Vec<FunctionComplexity>> {
+#[allow(dead_code)]
+fn get_function_complexities_from_flake8(_text: String) -> Result<Vec<FunctionComplexity>> {
     // Yep, the initial idea is to use flake's cognitive complexity linter flag
     Ok(vec![])
 }
@@ -445,4 +528,73 @@ mod test {
 
         assert_eq!(expected, cognitive_complex_index);
     }
+
+    #[test]
+    fn calculate_cognitive_complexity_of_code_in_memory() {
+        let simple_block_of_code = "
+            fn function() {
+                for i in 1..=10 { // 1 + 0 nesting
+                    if i == 10 { // 1 + 1 nesting
+                        println!(\"i = {i}\");
+                    }
+                }
+            } // Total: 3
+        ";
+
+        let expected = vec![FunctionComplexity {
+            function: "function".to_string(),
+            cognitive_complexity_value: 3,
+        }];
+
+        let cognitive_complex_index = compute_cognitive_index_of_code(
+            ProgrammingLang::Rust,
+            Path::new("src/lib.rs"),
+            simple_block_of_code,
+        )
+        .unwrap();
+
+        assert_eq!(expected, cognitive_complex_index);
+        assert!(compute_cognitive_index_of_code(
+            ProgrammingLang::Rust,
+            Path::new("README.md"),
+            simple_block_of_code
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn fail_to_evaluate_a_file_missing_from_the_working_tree() {
+        let complexities = compute_cognitive_index(ProgrammingLang::Rust, "src/deleted.rs".into());
+
+        assert!(complexities.is_err());
+    }
+
+    #[test]
+    fn find_the_lines_spanned_by_every_function() {
+        let code = "use std::io;
+
+#[inline]
+fn function() {
+    println!(\"function\");
+}
+
+fn function2() {}
+";
+
+        let expected = vec![
+            FunctionSpan {
+                function: "function".to_string(),
+                lines: 3..=6,
+            },
+            FunctionSpan {
+                function: "function2".to_string(),
+                lines: 8..=8,
+            },
+        ];
+
+        assert_eq!(
+            expected,
+            find_function_spans(ProgrammingLang::Rust, Path::new("lib.rs"), code).unwrap()
+        );
+    }
 }
@@ -0,0 +1,201 @@
+use crate::history::{ChangeHistoryProvider, HistoryQuery};
+use crate::{top_k_by, tracked_commits};
+use anyhow::Result;
+use futures_util::StreamExt;
+use itertools::Itertools;
+use std::collections::HashMap;
+
+// Commits changing more files than this, like mass renames or reformats, don't tell anything about coupling and
+// would add lots of pairs, so they are left out
+const MAX_CHANGESET_SIZE: usize = 50;
+
+/// How to relate the commits two files share to the commits of each one.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum CouplingDegree {
+    /// Shared commits divided by the commits of the file changing less
+    Min,
+    /// Shared commits divided by the average commits of both files
+    #[default]
+    Average,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CouplingOptions {
+    pub degree: CouplingDegree,
+    /// Files changed by fewer commits are left out, their coupling isn't meaningful
+    pub min_revisions: u32,
+    /// Pairs of files changed together by fewer commits are left out
+    pub min_shared_commits: u32,
+}
+
+impl Default for CouplingOptions {
+    fn default() -> Self {
+        CouplingOptions {
+            degree: CouplingDegree::default(),
+            min_revisions: 5,
+            min_shared_commits: 3,
+        }
+    }
+}
+
+/// Two files that tend to change together.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CoupledFiles {
+    pub filename: String,
+    pub coupled_filename: String,
+    pub shared_commits: u32,
+    /// Commits changing each of the files
+    pub revisions: (u32, u32),
+    /// From 0 (never changed together) to 1 (always changed together)
+    pub degree: f64,
+}
+
+#[async_trait::async_trait]
+pub trait ChangeCouplingExt {
+    async fn get_top_coupled_files(
+        &self,
+        num_of_pairs: usize,
+        query: &HistoryQuery,
+        options: &CouplingOptions,
+    ) -> Result<Vec<CoupledFiles>>;
+}
+
+#[async_trait::async_trait]
+impl<P> ChangeCouplingExt for P
+where
+    P: ChangeHistoryProvider + ?Sized,
+{
+    async fn get_top_coupled_files(
+        &self,
+        num_of_pairs: usize,
+        query: &HistoryQuery,
+        options: &CouplingOptions,
+    ) -> Result<Vec<CoupledFiles>> {
+        let mut revisions: HashMap<String, u32> = HashMap::new();
+        let mut shared_commits: HashMap<(String, String), u32> = HashMap::new();
+        let mut commits = tracked_commits(self, query).await?;
+        while let Some(commit) = commits.next().await {
+            let filenames: Vec<_> = commit
+                .files
+                .into_iter()
+                .map(|file_change| file_change.filename)
+                .sorted()
+                .dedup()
+                .collect();
+            for filename in &filenames {
+                *revisions.entry(filename.clone()).or_insert(0) += 1;
+            }
+            if filenames.len() > MAX_CHANGESET_SIZE {
+                continue;
+            }
+            for (filename, coupled_filename) in filenames.iter().tuple_combinations() {
+                *shared_commits
+                    .entry((filename.clone(), coupled_filename.clone()))
+                    .or_insert(0) += 1;
+            }
+        }
+
+        let coupled_files = shared_commits
+            .into_iter()
+            .filter(|(_, shared_commits)| *shared_commits >= options.min_shared_commits)
+            .filter_map(|((filename, coupled_filename), shared_commits)| {
+                let file_revisions = (revisions[&filename], revisions[&coupled_filename]);
+                if file_revisions.0.min(file_revisions.1) < options.min_revisions {
+                    return None;
+                }
+                let reference_revisions = match options.degree {
+                    CouplingDegree::Min => file_revisions.0.min(file_revisions.1) as f64,
+                    CouplingDegree::Average => (file_revisions.0 + file_revisions.1) as f64 / 2.0,
+                };
+                Some(CoupledFiles {
+                    filename,
+                    coupled_filename,
+                    shared_commits,
+                    revisions: file_revisions,
+                    degree: shared_commits as f64 / reference_revisions,
+                })
+            });
+        // Pairs coupled as much are listed by name, as the map has no order
+        Ok(top_k_by(coupled_files, num_of_pairs, |pair1, pair2| {
+            pair1
+                .degree
+                .total_cmp(&pair2.degree)
+                .then(pair1.shared_commits.cmp(&pair2.shared_commits))
+                .then_with(|| pair2.filename.cmp(&pair1.filename))
+                .then_with(|| pair2.coupled_filename.cmp(&pair1.coupled_filename))
+        }))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::history::CommitChanges;
+
+    fn commit(filenames: &[&str]) -> CommitChanges {
+        CommitChanges::changing(&filenames.join("+"), filenames)
+    }
+
+    #[tokio::test]
+    async fn find_the_files_changing_together() {
+        let history = vec![
+            commit(&["parser.rs", "lexer.rs"]),
+            commit(&["parser.rs", "lexer.rs", "README.md"]),
+            commit(&["parser.rs", "lexer.rs"]),
+            commit(&["parser.rs", "main.rs"]),
+            commit(&["main.rs", "README.md"]),
+            commit(&["lexer.rs"]),
+        ];
+        let options = CouplingOptions {
+            degree: CouplingDegree::Min,
+            min_revisions: 2,
+            min_shared_commits: 1,
+        };
+
+        let coupled_files = history
+            .get_top_coupled_files(3, &HistoryQuery::default(), &options)
+            .await
+            .unwrap();
+
+        let expected = vec![
+            CoupledFiles {
+                filename: "lexer.rs".into(),
+                coupled_filename: "parser.rs".into(),
+                shared_commits: 3,
+                revisions: (4, 4),
+                degree: 0.75,
+            },
+            CoupledFiles {
+                filename: "README.md".into(),
+                coupled_filename: "lexer.rs".into(),
+                shared_commits: 1,
+                revisions: (2, 4),
+                degree: 0.5,
+            },
+            CoupledFiles {
+                filename: "README.md".into(),
+                coupled_filename: "main.rs".into(),
+                shared_commits: 1,
+                revisions: (2, 2),
+                degree: 0.5,
+            },
+        ];
+        assert_eq!(expected, coupled_files);
+    }
+
+    #[tokio::test]
+    async fn leave_out_the_pairs_below_the_thresholds() {
+        let history = vec![
+            commit(&["parser.rs", "lexer.rs"]),
+            commit(&["parser.rs", "lexer.rs"]),
+            commit(&["parser.rs", "main.rs"]),
+        ];
+
+        let coupled_files = history
+            .get_top_coupled_files(10, &HistoryQuery::default(), &CouplingOptions::default())
+            .await
+            .unwrap();
+
+        assert!(coupled_files.is_empty());
+    }
+}
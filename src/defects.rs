@@ -0,0 +1,269 @@
+use crate::history::{ChangeHistoryProvider, CommitChanges, HistoryQuery};
+use crate::{aggregate_file_changes, measure_file_sizes, top_k_by, ChangedFileStats};
+use anyhow::Result;
+use regex::Regex;
+use std::collections::HashMap;
+use std::sync::{LazyLock, Mutex};
+
+static ISSUE_REFERENCE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"#(\d+)").expect("Invalid issue reference regex"));
+
+/// Commit messages matching any of these are bug fixes, unless other patterns are given.
+pub const DEFAULT_BUG_FIX_PATTERNS: &[&str] = &[r"(?i)\b(fix(es|ed)?|bug|defect|hotfix)\b"];
+
+/// Tells which commits fix bugs, from their messages and, optionally, from the labels of the issues they link.
+pub struct BugFixClassifier {
+    message_patterns: Vec<Regex>,
+    /// Commits referencing an issue or pull request (`#123`) with this label are bug fixes too
+    issue_label: Option<String>,
+    // Whether every issue looked up so far has the label, many commits can reference the same issue. Failed
+    // lookups aren't kept, they may work for the next commit
+    labeled_issues: Mutex<HashMap<u64, bool>>,
+}
+
+impl Default for BugFixClassifier {
+    fn default() -> Self {
+        let patterns: Vec<_> = DEFAULT_BUG_FIX_PATTERNS
+            .iter()
+            .map(|pattern| pattern.to_string())
+            .collect();
+        BugFixClassifier::new(&patterns, None).expect("Invalid default bug fix patterns")
+    }
+}
+
+impl BugFixClassifier {
+    pub fn new(message_patterns: &[String], issue_label: Option<&str>) -> Result<Self> {
+        Ok(BugFixClassifier {
+            message_patterns: message_patterns
+                .iter()
+                .map(|pattern| Regex::new(pattern))
+                .collect::<Result<_, _>>()?,
+            issue_label: issue_label.map(str::to_lowercase),
+            labeled_issues: Mutex::new(HashMap::new()),
+        })
+    }
+
+    pub async fn is_bug_fix<P>(&self, history: &P, commit: &CommitChanges) -> bool
+    where
+        P: ChangeHistoryProvider + ?Sized,
+    {
+        if self
+            .message_patterns
+            .iter()
+            .any(|pattern| pattern.is_match(&commit.message))
+        {
+            return true;
+        }
+        let Some(issue_label) = &self.issue_label else {
+            return false;
+        };
+
+        for captures in ISSUE_REFERENCE.captures_iter(&commit.message) {
+            let Ok(number) = captures[1].parse() else {
+                continue;
+            };
+            let cached = self.labeled_issues.lock().unwrap().get(&number).copied();
+            let is_labeled = match cached {
+                Some(is_labeled) => is_labeled,
+                None => match history.issue_labels(number).await {
+                    Ok(labels) => {
+                        let is_labeled = labels
+                            .iter()
+                            .any(|label| label.to_lowercase() == *issue_label);
+                        self.labeled_issues
+                            .lock()
+                            .unwrap()
+                            .insert(number, is_labeled);
+                        is_labeled
+                    }
+                    // Issues we can't look up, like references to other repositories, don't make bug fixes
+                    Err(error) => {
+                        log::warn!(
+                            "Couldn't look up the labels of issue #{number}, referenced by {}: {error:#}",
+                            commit.sha
+                        );
+                        false
+                    }
+                },
+            };
+            if is_labeled {
+                return true;
+            }
+        }
+        false
+    }
+}
+
+/// The files changed by most bug fixes in the analysis window, with their size measured.
+pub async fn get_top_defect_prone_files<P>(
+    history: &P,
+    num_of_files: usize,
+    query: &HistoryQuery,
+    classifier: &BugFixClassifier,
+) -> Result<Vec<ChangedFileStats>>
+where
+    P: ChangeHistoryProvider + ?Sized,
+{
+    let mut defect_prone_files = top_k_by(
+        aggregate_file_changes(history, query, Some(classifier)).await?,
+        num_of_files,
+        |file1, file2| {
+            (file1.num_bug_fixes, file1.num_commits).cmp(&(file2.num_bug_fixes, file2.num_commits))
+        },
+    );
+    measure_file_sizes(history, &mut defect_prone_files, query.git_ref.as_deref()).await;
+    Ok(defect_prone_files)
+}
+
+/// Pearson correlation of the pairs of values, from -1 to 1. `None` if there aren't enough values or any of them
+/// doesn't vary.
+pub fn correlation(values: &[(f64, f64)]) -> Option<f64> {
+    if values.len() < 2 {
+        return None;
+    }
+    let count = values.len() as f64;
+    let mean_x = values.iter().map(|(x, _)| x).sum::<f64>() / count;
+    let mean_y = values.iter().map(|(_, y)| y).sum::<f64>() / count;
+    let covariance: f64 = values
+        .iter()
+        .map(|(x, y)| (x - mean_x) * (y - mean_y))
+        .sum();
+    let variance_x: f64 = values.iter().map(|(x, _)| (x - mean_x).powi(2)).sum();
+    let variance_y: f64 = values.iter().map(|(_, y)| (y - mean_y).powi(2)).sum();
+    match variance_x > 0.0 && variance_y > 0.0 {
+        true => Some(covariance / (variance_x * variance_y).sqrt()),
+        false => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use anyhow::anyhow;
+    use chrono::{DateTime, Utc};
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    /// Recorded history whose issue #1 is labeled as a bug.
+    struct LabeledHistory(Vec<CommitChanges>);
+
+    #[async_trait::async_trait]
+    impl ChangeHistoryProvider for LabeledHistory {
+        async fn commits<'a>(
+            &'a self,
+            query: &HistoryQuery,
+        ) -> Result<crate::history::CommitStream<'a>> {
+            self.0.commits(query).await
+        }
+
+        async fn commit_date(&self, git_ref: &str) -> Result<DateTime<Utc>> {
+            self.0.commit_date(git_ref).await
+        }
+
+        async fn file_contents(&self, path: &str, _git_ref: Option<&str>) -> Result<String> {
+            match path {
+                "src/parser.rs" => Ok("fn parse() {\n}\n".into()),
+                _ => Err(anyhow!("{path} not found")),
+            }
+        }
+
+        async fn issue_labels(&self, number: u64) -> Result<Vec<String>> {
+            match number {
+                1 => Ok(vec!["Bug".into(), "parser".into()]),
+                _ => Ok(vec!["enhancement".into()]),
+            }
+        }
+    }
+
+    fn commit(message: &str, filenames: &[&str]) -> CommitChanges {
+        CommitChanges {
+            message: message.into(),
+            ..CommitChanges::changing(message, filenames)
+        }
+    }
+
+    #[tokio::test]
+    async fn count_the_bug_fixes_of_every_file() {
+        let history = LabeledHistory(vec![
+            commit("Fix crash on empty input", &["src/parser.rs"]),
+            commit("Handle unicode (#1)", &["src/parser.rs", "src/lexer.rs"]),
+            commit("Add the lexer (#2)", &["src/lexer.rs"]),
+            commit("Prefix the output", &["src/main.rs"]),
+        ]);
+        let classifier = BugFixClassifier::new(&[r"(?i)\bfix".into()], Some("bug")).unwrap();
+
+        let defect_prone_files =
+            get_top_defect_prone_files(&history, 2, &HistoryQuery::default(), &classifier)
+                .await
+                .unwrap();
+
+        let bug_fixes: Vec<_> = defect_prone_files
+            .iter()
+            .map(|file| (file.filename.as_str(), file.num_bug_fixes, file.num_lines))
+            .collect();
+        assert_eq!(
+            vec![("src/parser.rs", 2, Some(2)), ("src/lexer.rs", 1, None)],
+            bug_fixes
+        );
+        assert_eq!(Some(1000.0), defect_prone_files[0].defect_density());
+    }
+
+    /// Recorded history whose issue #1 is labeled as a bug, but the first lookup of its labels fails.
+    struct FlakyLabelsHistory {
+        commits: Vec<CommitChanges>,
+        failed_lookup: AtomicBool,
+    }
+
+    #[async_trait::async_trait]
+    impl ChangeHistoryProvider for FlakyLabelsHistory {
+        async fn commits<'a>(
+            &'a self,
+            query: &HistoryQuery,
+        ) -> Result<crate::history::CommitStream<'a>> {
+            self.commits.commits(query).await
+        }
+
+        async fn commit_date(&self, git_ref: &str) -> Result<DateTime<Utc>> {
+            self.commits.commit_date(git_ref).await
+        }
+
+        async fn file_contents(&self, path: &str, _git_ref: Option<&str>) -> Result<String> {
+            Err(anyhow!("{path} not found"))
+        }
+
+        async fn issue_labels(&self, _number: u64) -> Result<Vec<String>> {
+            match self.failed_lookup.swap(true, Ordering::SeqCst) {
+                false => Err(anyhow!("502 Bad Gateway")),
+                true => Ok(vec!["bug".into()]),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn look_up_the_labels_again_after_a_failed_lookup() {
+        let history = FlakyLabelsHistory {
+            commits: vec![
+                commit("Handle unicode (#1)", &["src/parser.rs"]),
+                commit("Handle emojis (#1)", &["src/parser.rs"]),
+            ],
+            failed_lookup: AtomicBool::new(false),
+        };
+        let classifier = BugFixClassifier::new(&[], Some("bug")).unwrap();
+
+        let mut bug_fixes = vec![];
+        for commit in &history.commits {
+            bug_fixes.push(classifier.is_bug_fix(&history, commit).await);
+        }
+
+        assert_eq!(vec![false, true], bug_fixes);
+    }
+
+    #[test]
+    fn correlate_complexity_and_defects() {
+        assert_eq!(
+            Some(1.0),
+            correlation(&[(1.0, 2.0), (2.0, 4.0), (3.0, 6.0)])
+        );
+        assert_eq!(Some(-1.0), correlation(&[(1.0, 2.0), (2.0, 1.0)]));
+        assert_eq!(None, correlation(&[(1.0, 2.0), (1.0, 4.0)]));
+    }
+}
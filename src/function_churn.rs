@@ -0,0 +1,266 @@
+use crate::complexity::{find_function_spans, FunctionSpan, ProgrammingLang};
+use crate::TopComplexities;
+use anyhow::Result;
+use complexity_radar::history::{ChangeHistoryProvider, HistoryQuery};
+use complexity_radar::tracked_commits_with_paths;
+use futures_util::StreamExt;
+use itertools::Itertools;
+use std::collections::HashMap;
+use std::ops::RangeInclusive;
+
+/// How often a function changed in the analysis window, together with its current complexity.
+pub struct FunctionChurn {
+    pub function: String,
+    pub filename: String,
+    pub num_changes: u32,
+    pub cognitive_complexity_value: u16,
+}
+
+/// Functions whose lines overlap any of the changed lines.
+fn functions_changed<'a>(
+    function_spans: &'a [FunctionSpan],
+    changed_lines: &'a [RangeInclusive<u32>],
+) -> impl Iterator<Item = &'a str> {
+    function_spans
+        .iter()
+        .filter(|function_span| {
+            changed_lines.iter().any(|changed_lines| {
+                changed_lines.start() <= function_span.lines.end()
+                    && function_span.lines.start() <= changed_lines.end()
+            })
+        })
+        .map(|function_span| function_span.function.as_str())
+}
+
+/// Functions of the file at `path` as it was after the commit `sha`, empty if it was deleted or can't be parsed.
+/// Every revision of a file is only fetched once, even if it's listed more than once.
+async fn function_spans_at<'a>(
+    history: &dyn ChangeHistoryProvider,
+    function_spans: &'a mut HashMap<(String, String), Vec<FunctionSpan>>,
+    path: &str,
+    sha: &str,
+) -> &'a [FunctionSpan] {
+    let key = (path.to_string(), sha.to_string());
+    if !function_spans.contains_key(&key) {
+        let spans = match history.file_contents(path, Some(sha)).await {
+            Ok(code) => {
+                find_function_spans(ProgrammingLang::Rust, path.as_ref(), &code).unwrap_or_default()
+            }
+            Err(_) => vec![],
+        };
+        function_spans.insert(key.clone(), spans);
+    }
+    &function_spans[&key]
+}
+
+/// Counts how many commits changed every function of the given files, mapping the hunks of every commit to the
+/// functions of the file as it was after that commit. Files are named by their current path.
+pub async fn count_function_changes(
+    history: &dyn ChangeHistoryProvider,
+    query: &HistoryQuery,
+    filenames: &[String],
+) -> Result<HashMap<(String, String), u32>> {
+    let mut function_changes = HashMap::new();
+    let mut function_spans = HashMap::new();
+    let hunks_query = HistoryQuery {
+        changed_lines: true,
+        ..query.clone()
+    };
+    let mut commits = tracked_commits_with_paths(history, &hunks_query).await?;
+
+    while let Some((commit, paths_at_commit)) = commits.next().await {
+        // The file has to be read by the path it had at that commit, but counted under its current one
+        for (path_at_commit, file_change) in paths_at_commit.iter().zip(&commit.files) {
+            if file_change.changed_lines.is_empty() || !filenames.contains(&file_change.filename) {
+                continue;
+            }
+            let function_spans =
+                function_spans_at(history, &mut function_spans, path_at_commit, &commit.sha).await;
+            for function in functions_changed(function_spans, &file_change.changed_lines) {
+                *function_changes
+                    .entry((file_change.filename.clone(), function.to_string()))
+                    .or_insert(0) += 1;
+            }
+        }
+    }
+
+    Ok(function_changes)
+}
+
+/// Joins the function changes with the current complexity of the functions, and sorts them by changes. Functions
+/// that don't exist anymore are left out.
+pub fn rank_function_churn(
+    function_changes: &HashMap<(String, String), u32>,
+    top_complexities: &[TopComplexities],
+) -> Vec<FunctionChurn> {
+    top_complexities
+        .iter()
+        .flat_map(|top_complexities| {
+            top_complexities
+                .function_complexities
+                .iter()
+                .filter_map(|function_complexity| {
+                    let filename = &top_complexities.changes.filename;
+                    let key = (filename.clone(), function_complexity.function.clone());
+                    function_changes.get(&key).map(|num_changes| FunctionChurn {
+                        function: function_complexity.function.clone(),
+                        filename: filename.clone(),
+                        num_changes: *num_changes,
+                        cognitive_complexity_value: function_complexity.cognitive_complexity_value,
+                    })
+                })
+        })
+        .sorted_by(|churn1, churn2| {
+            (churn2.num_changes, churn2.cognitive_complexity_value)
+                .cmp(&(churn1.num_changes, churn1.cognitive_complexity_value))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::complexity::FunctionComplexity;
+    use anyhow::anyhow;
+    use chrono::{DateTime, Utc};
+    use complexity_radar::history::{CommitChanges, CommitStream, FileChange};
+    use complexity_radar::ChangedFileStats;
+    use std::sync::Mutex;
+
+    const CODE: &str = "fn parse() {\n}\n\nfn eval() {\n}\n";
+
+    /// Recorded history that also keeps the contents of the files, and remembers which revisions were read.
+    struct RecordedRepository {
+        commits: Vec<CommitChanges>,
+        contents: HashMap<(String, String), String>,
+        reads: Mutex<Vec<(String, String)>>,
+    }
+
+    #[async_trait::async_trait]
+    impl ChangeHistoryProvider for RecordedRepository {
+        async fn commits<'a>(&'a self, query: &HistoryQuery) -> Result<CommitStream<'a>> {
+            self.commits.commits(query).await
+        }
+
+        async fn commit_date(&self, git_ref: &str) -> Result<DateTime<Utc>> {
+            self.commits.commit_date(git_ref).await
+        }
+
+        async fn file_contents(&self, path: &str, git_ref: Option<&str>) -> Result<String> {
+            let key = (path.to_string(), git_ref.unwrap_or_default().to_string());
+            self.reads.lock().unwrap().push(key.clone());
+            self.contents
+                .get(&key)
+                .cloned()
+                .ok_or_else(|| anyhow!("{path} doesn't exist at {git_ref:?}"))
+        }
+    }
+
+    fn commit(sha: &str, files: &[(&str, Option<&str>, RangeInclusive<u32>)]) -> CommitChanges {
+        CommitChanges {
+            sha: sha.into(),
+            files: files
+                .iter()
+                .map(|(filename, previous_filename, changed_lines)| FileChange {
+                    filename: filename.to_string(),
+                    previous_filename: previous_filename.map(String::from),
+                    changed_lines: vec![changed_lines.clone()],
+                    ..Default::default()
+                })
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    fn function_span(function: &str, lines: RangeInclusive<u32>) -> FunctionSpan {
+        FunctionSpan {
+            function: function.into(),
+            lines,
+        }
+    }
+
+    #[test]
+    fn find_the_functions_touched_by_the_changed_lines() {
+        let function_spans = vec![
+            function_span("first", 1..=10),
+            function_span("second", 12..=20),
+            function_span("third", 22..=40),
+        ];
+
+        let changed: Vec<_> = functions_changed(&function_spans, &[10..=12, 41..=45]).collect();
+
+        assert_eq!(vec!["first", "second"], changed);
+    }
+
+    #[tokio::test]
+    async fn count_the_changes_of_a_renamed_file_reading_every_revision_once() {
+        // The file was renamed by the newest commit, and listed twice by the oldest one
+        let repository = RecordedRepository {
+            commits: vec![
+                commit("c2", &[("src/radar.rs", Some("lib.rs"), 1..=1)]),
+                commit("c1", &[("lib.rs", None, 1..=1), ("lib.rs", None, 4..=5)]),
+            ],
+            contents: HashMap::from([
+                (("src/radar.rs".into(), "c2".into()), CODE.into()),
+                (("lib.rs".into(), "c1".into()), CODE.into()),
+            ]),
+            reads: Mutex::default(),
+        };
+
+        let function_changes = count_function_changes(
+            &repository,
+            &HistoryQuery::default(),
+            &["src/radar.rs".into()],
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(
+            HashMap::from([
+                (("src/radar.rs".to_string(), "parse".to_string()), 2),
+                (("src/radar.rs".to_string(), "eval".to_string()), 1),
+            ]),
+            function_changes
+        );
+        assert_eq!(
+            vec![
+                ("src/radar.rs".to_string(), "c2".to_string()),
+                ("lib.rs".to_string(), "c1".to_string())
+            ],
+            *repository.reads.lock().unwrap()
+        );
+    }
+
+    #[test]
+    fn rank_the_functions_by_changes_with_their_complexity() {
+        let top_complexities = vec![TopComplexities {
+            changes: ChangedFileStats {
+                filename: "src/lib.rs".into(),
+                num_commits: 10,
+                lines_changed: 100,
+                num_lines: Some(3000),
+                ..Default::default()
+            },
+            function_complexities: ["parse", "eval", "unchanged"]
+                .iter()
+                .map(|function| FunctionComplexity {
+                    function: function.to_string(),
+                    cognitive_complexity_value: 5,
+                })
+                .collect(),
+        }];
+        let function_changes = HashMap::from([
+            (("src/lib.rs".to_string(), "parse".to_string()), 2),
+            (("src/lib.rs".to_string(), "eval".to_string()), 7),
+            (("src/lib.rs".to_string(), "removed".to_string()), 9),
+        ]);
+
+        let function_churn = rank_function_churn(&function_changes, &top_complexities);
+
+        let ranking: Vec<_> = function_churn
+            .iter()
+            .map(|churn| (churn.function.as_str(), churn.num_changes))
+            .collect();
+        assert_eq!(vec![("eval", 7), ("parse", 2)], ranking);
+    }
+}
@@ -0,0 +1,242 @@
+use super::{
+    fetch_commits, paginate, ChangeHistoryProvider, CommitChanges, CommitStream, FileChange,
+    ForgeOptions, HistoryQuery, PagedApi, WithForgeOptions,
+};
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use futures::future;
+use futures_util::{StreamExt, TryStreamExt};
+use serde::de::{DeserializeOwned, IgnoredAny};
+use serde::Deserialize;
+
+const PAGE_SIZE: u64 = 100;
+
+/// Change history of a repository hosted on a Bitbucket Server (or Data Center) instance.
+///
+/// The changes listing doesn't report the lines changed per file, so rankings by lines changed don't work.
+pub struct BitbucketHistory {
+    client: reqwest::Client,
+    base_url: String,
+    project_key: String,
+    repo_slug: String,
+    token: String,
+    options: ForgeOptions,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct BitbucketPage<T> {
+    values: Vec<T>,
+    is_last_page: bool,
+    next_page_start: Option<u64>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct BitbucketCommit {
+    id: String,
+    #[serde(with = "chrono::serde::ts_milliseconds")]
+    author_timestamp: DateTime<Utc>,
+    #[serde(with = "chrono::serde::ts_milliseconds")]
+    committer_timestamp: DateTime<Utc>,
+    author: Option<BitbucketAuthor>,
+    #[serde(default)]
+    message: String,
+    parents: Option<Vec<IgnoredAny>>,
+}
+
+#[derive(Deserialize)]
+struct BitbucketAuthor {
+    name: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct BitbucketChange {
+    path: BitbucketPath,
+    src_path: Option<BitbucketPath>,
+    #[serde(rename = "type")]
+    change_type: String,
+}
+
+#[derive(Deserialize)]
+struct BitbucketPath {
+    #[serde(rename = "toString")]
+    full_path: String,
+}
+
+impl BitbucketHistory {
+    pub fn new(base_url: &str, project_key: &str, repo_slug: &str, token: &str) -> Self {
+        BitbucketHistory {
+            client: reqwest::Client::new(),
+            base_url: base_url.trim_end_matches('/').to_string(),
+            project_key: project_key.to_string(),
+            repo_slug: repo_slug.to_string(),
+            token: token.to_string(),
+            options: ForgeOptions::default(),
+        }
+    }
+
+    fn repo_url(&self) -> String {
+        format!(
+            "{}/rest/api/1.0/projects/{}/repos/{}",
+            self.base_url, self.project_key, self.repo_slug
+        )
+    }
+
+    /// Fetches the page of the commits listing starting at `start`. Bitbucket lists commits in topological order, so
+    /// a merged commit can have been committed long before the ones listed after it: the listing only ends after a
+    /// page committed entirely before `since`, rather than at the first commit out of the window.
+    async fn get_commits_page(
+        &self,
+        url: &str,
+        params: &[(&str, String)],
+        start: u64,
+        since: DateTime<Utc>,
+    ) -> Result<(Vec<BitbucketCommit>, Option<u64>)> {
+        let (commits, next_page_start): (Vec<BitbucketCommit>, _) =
+            self.get_page(url, params, start).await?;
+        let window_left = commits
+            .iter()
+            .any(|commit| commit.committer_timestamp >= since);
+        Ok((commits, next_page_start.filter(|_| window_left)))
+    }
+
+    async fn get_commit_changes(&self, commit: BitbucketCommit) -> Result<CommitChanges> {
+        let sha = commit.id;
+        let changes_url = format!("{}/commits/{}/changes", self.repo_url(), sha);
+        let files = self
+            .list::<BitbucketChange>(changes_url, vec![])
+            .await?
+            .map_ok(|change| FileChange {
+                previous_filename: match change.change_type.as_str() {
+                    "MOVE" => change.src_path.map(|src_path| src_path.full_path),
+                    _ => None,
+                },
+                filename: change.path.full_path,
+                ..Default::default()
+            })
+            .try_collect()
+            .await?;
+        Ok(CommitChanges {
+            sha,
+            author: commit.author.map(|author| author.name),
+            date: Some(commit.author_timestamp),
+            message: commit.message,
+            files,
+            num_parents: commit.parents.map(|parents| parents.len() as u32),
+        })
+    }
+}
+
+#[async_trait::async_trait]
+impl PagedApi for BitbucketHistory {
+    const FIRST_PAGE: u64 = 0;
+
+    async fn get_page<T: DeserializeOwned + Send>(
+        &self,
+        url: &str,
+        query: &[(&str, String)],
+        start: u64,
+    ) -> Result<(Vec<T>, Option<u64>)> {
+        let page: BitbucketPage<T> = self
+            .client
+            .get(url)
+            .bearer_auth(&self.token)
+            .query(query)
+            .query(&[("start", start), ("limit", PAGE_SIZE)])
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+
+        let next_page_start = match page.is_last_page {
+            true => None,
+            false => page.next_page_start,
+        };
+        Ok((page.values, next_page_start))
+    }
+}
+
+impl WithForgeOptions for BitbucketHistory {
+    fn forge_options(&mut self) -> &mut ForgeOptions {
+        &mut self.options
+    }
+}
+
+#[async_trait::async_trait]
+impl ChangeHistoryProvider for BitbucketHistory {
+    async fn commits<'a>(&'a self, query: &HistoryQuery) -> Result<CommitStream<'a>> {
+        let commits_url = format!("{}/commits", self.repo_url());
+        let (since, window) = (query.since, query.clone());
+        // Bitbucket calls `until` the ref to start listing commits from
+        let params: Vec<_> = query
+            .git_ref
+            .iter()
+            .map(|git_ref| ("until", git_ref.clone()))
+            .collect();
+
+        // Bitbucket can't filter commits by date, so we filter the listing on the author dates, which decide what's in
+        // the analysis window, and stop it on a page boundary once no commit was committed in the window anymore
+        let first_page = self
+            .get_commits_page(&commits_url, &params, Self::FIRST_PAGE, since)
+            .await?;
+        let commits_stream = paginate(first_page, move |start| {
+            let (commits_url, params) = (commits_url.clone(), params.clone());
+            async move {
+                self.get_commits_page(&commits_url, &params, start, since)
+                    .await
+            }
+        })
+        .try_filter(move |commit| future::ready(window.contains(commit.author_timestamp)));
+        Ok(fetch_commits(
+            commits_stream.boxed(),
+            query,
+            &self.options,
+            |commit| commit.id.clone(),
+            move |commit| self.get_commit_changes(commit),
+        ))
+    }
+
+    async fn commit_date(&self, git_ref: &str) -> Result<DateTime<Utc>> {
+        let commit: BitbucketCommit = self
+            .client
+            .get(format!("{}/commits/{}", self.repo_url(), git_ref))
+            .bearer_auth(&self.token)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        Ok(commit.committer_timestamp)
+    }
+
+    async fn last_commit_before(
+        &self,
+        git_ref: Option<&str>,
+        date: DateTime<Utc>,
+    ) -> Result<Option<String>> {
+        let params: Vec<_> = git_ref
+            .iter()
+            .map(|git_ref| ("until", git_ref.to_string()))
+            .collect();
+        // Bitbucket can't filter commits by date, we skip the ones committed after it
+        let mut commits = self
+            .list::<BitbucketCommit>(format!("{}/commits", self.repo_url()), params)
+            .await?
+            .try_skip_while(move |commit| future::ready(Ok(commit.committer_timestamp > date)));
+        Ok(commits.try_next().await?.map(|commit| commit.id))
+    }
+
+    async fn file_contents(&self, path: &str, git_ref: Option<&str>) -> Result<String> {
+        let mut request = self
+            .client
+            .get(format!("{}/raw/{}", self.repo_url(), path))
+            .bearer_auth(&self.token);
+        if let Some(git_ref) = git_ref {
+            request = request.query(&[("at", git_ref)]);
+        }
+        Ok(request.send().await?.error_for_status()?.text().await?)
+    }
+}
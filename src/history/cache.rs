@@ -0,0 +1,85 @@
+use super::CommitChanges;
+use anyhow::Result;
+use std::path::{Path, PathBuf};
+
+/// Commits fetched in previous runs, one JSON file per SHA. Commits never change, so they never expire.
+#[derive(Debug, Clone)]
+pub struct CommitCache {
+    directory: PathBuf,
+}
+
+impl CommitCache {
+    /// Cache of the commits of one repository, under `directory`.
+    pub fn new(directory: PathBuf) -> Self {
+        CommitCache { directory }
+    }
+
+    /// Where the commits of every repository are cached by default: `$XDG_CACHE_HOME/complexity-radar`, or the
+    /// platform equivalent.
+    pub fn default_root() -> Option<PathBuf> {
+        dirs::cache_dir().map(|cache_dir| cache_dir.join("complexity-radar"))
+    }
+
+    pub fn directory(&self) -> &Path {
+        &self.directory
+    }
+
+    fn path(&self, sha: &str) -> PathBuf {
+        self.directory.join(format!("{sha}.json"))
+    }
+
+    /// The cached commit, `None` if it isn't cached or the cache entry is unreadable.
+    pub async fn load(&self, sha: &str) -> Option<CommitChanges> {
+        let json = tokio::fs::read(self.path(sha)).await.ok()?;
+        serde_json::from_slice(&json).ok()
+    }
+
+    pub async fn store(&self, commit: &CommitChanges) -> Result<()> {
+        tokio::fs::create_dir_all(&self.directory).await?;
+        // Written aside and moved in place, so an interrupted run doesn't leave half a commit behind
+        let path = self.path(&commit.sha);
+        let partial_path = path.with_extension("json.partial");
+        tokio::fs::write(&partial_path, serde_json::to_vec(commit)?).await?;
+        tokio::fs::rename(partial_path, path).await?;
+        Ok(())
+    }
+
+    /// Removes every cached commit of the repository.
+    pub async fn clear(&self) -> Result<()> {
+        match tokio::fs::remove_dir_all(&self.directory).await {
+            Err(error) if error.kind() != std::io::ErrorKind::NotFound => Err(error.into()),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::history::FileChange;
+
+    #[tokio::test]
+    async fn store_and_load_commits_until_cleared() {
+        let directory = tempfile::tempdir().unwrap();
+        let cache = CommitCache::new(directory.path().join("github.com/owner/repo"));
+        let commit = CommitChanges {
+            sha: "abc".into(),
+            author: Some("Ada".into()),
+            message: "Fix the parser".into(),
+            files: vec![FileChange {
+                filename: "src/parser.rs".into(),
+                additions: 3,
+                changed_lines: vec![10..=12],
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+
+        assert_eq!(None, cache.load("abc").await);
+        cache.store(&commit).await.unwrap();
+        assert_eq!(Some(commit), cache.load("abc").await);
+        cache.clear().await.unwrap();
+        assert_eq!(None, cache.load("abc").await);
+        cache.clear().await.unwrap();
+    }
+}
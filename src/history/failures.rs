@@ -0,0 +1,79 @@
+use std::sync::{Arc, Mutex};
+
+/// Context of the errors fetching the changes of a listed commit, to tell which one failed.
+#[derive(Debug, thiserror::Error)]
+#[error("Couldn't fetch commit {sha}")]
+pub struct CommitFetchError {
+    pub sha: String,
+}
+
+/// A commit whose changes couldn't be fetched.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FailedCommit {
+    /// `None` when listing the commits failed, so we don't know which ones are missing
+    pub sha: Option<String>,
+    pub error: String,
+}
+
+/// Commits left out of the analysis because they couldn't be fetched. Clones share the same list, so every
+/// analysis of a run adds to it.
+#[derive(Debug, Clone, Default)]
+pub struct FailedCommits(Arc<Mutex<Vec<FailedCommit>>>);
+
+impl FailedCommits {
+    /// Records the error of a commit stream. Commits failing in more than one analysis are only recorded once.
+    pub fn record(&self, error: &anyhow::Error) {
+        let failed_commit = FailedCommit {
+            sha: error
+                .downcast_ref::<CommitFetchError>()
+                .map(|fetch_error| fetch_error.sha.clone()),
+            error: error.root_cause().to_string(),
+        };
+        let mut failed_commits = self.0.lock().unwrap();
+        if failed_commit.sha.is_none() || !failed_commits.contains(&failed_commit) {
+            failed_commits.push(failed_commit);
+        }
+    }
+
+    pub fn to_vec(&self) -> Vec<FailedCommit> {
+        self.0.lock().unwrap().clone()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.lock().unwrap().is_empty()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use anyhow::{anyhow, Context};
+
+    #[test]
+    fn record_every_failed_commit_once_across_clones() {
+        let failed_commits = FailedCommits::default();
+        let fetch_error = || {
+            Err::<(), _>(anyhow!("502 Bad Gateway"))
+                .context(CommitFetchError { sha: "abc".into() })
+                .unwrap_err()
+        };
+
+        failed_commits.record(&fetch_error());
+        failed_commits.clone().record(&fetch_error());
+        failed_commits.record(&anyhow!("Connection reset"));
+
+        assert_eq!(
+            vec![
+                FailedCommit {
+                    sha: Some("abc".into()),
+                    error: "502 Bad Gateway".into(),
+                },
+                FailedCommit {
+                    sha: None,
+                    error: "Connection reset".into(),
+                },
+            ],
+            failed_commits.to_vec()
+        );
+    }
+}
@@ -0,0 +1,179 @@
+use super::CommitChanges;
+use anyhow::Result;
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use regex::{Regex, RegexSet};
+
+/// Name of the repository file listing the paths to leave out of the analysis, with gitignore syntax.
+pub const IGNORE_FILENAME: &str = ".complexityradarignore";
+
+/// Decides which files are taken into account when counting changes, so lock files, changelogs, generated or
+/// vendored code don't crowd out the files we care about.
+#[derive(Debug, Clone)]
+pub struct PathFilter {
+    include: Option<GlobSet>,
+    exclude: Gitignore,
+}
+
+impl Default for PathFilter {
+    /// Takes every file into account
+    fn default() -> Self {
+        PathFilter {
+            include: None,
+            exclude: Gitignore::empty(),
+        }
+    }
+}
+
+impl PathFilter {
+    /// `include` are globs (like `src/**/*.rs`) the files must match, all of them if empty. `exclude` patterns and
+    /// the lines of the `ignore_file` contents follow the gitignore syntax.
+    pub fn new(include: &[String], exclude: &[String], ignore_file: Option<&str>) -> Result<Self> {
+        let include = match include.is_empty() {
+            true => None,
+            false => {
+                let mut include_builder = GlobSetBuilder::new();
+                for glob in include {
+                    include_builder.add(Glob::new(glob)?);
+                }
+                Some(include_builder.build()?)
+            }
+        };
+
+        let mut exclude_builder = GitignoreBuilder::new("");
+        for pattern in ignore_file
+            .into_iter()
+            .flat_map(str::lines)
+            .chain(exclude.iter().map(String::as_str))
+        {
+            exclude_builder.add_line(None, pattern)?;
+        }
+
+        Ok(PathFilter {
+            include,
+            exclude: exclude_builder.build()?,
+        })
+    }
+
+    pub fn is_included(&self, path: &str) -> bool {
+        let included = match &self.include {
+            Some(include) => include.is_match(path),
+            None => true,
+        };
+        included
+            && !self
+                .exclude
+                .matched_path_or_any_parents(path, false)
+                .is_ignore()
+    }
+}
+
+/// Decides which commits are counted, so merges, bots and mass changes like reformatting don't inflate the churn
+/// of the files they touch, like `Cargo.lock` or changelogs. Every commit is counted by default.
+#[derive(Debug, Clone, Default)]
+pub struct CommitFilter {
+    skip_merges: bool,
+    excluded_authors: Option<RegexSet>,
+    excluded_message: Option<Regex>,
+    max_files: Option<usize>,
+}
+
+impl CommitFilter {
+    /// `excluded_authors` are author names where `*` matches anything, like `*[bot]`. `excluded_message` is a
+    /// regex found anywhere in the message. Commits changing more than `max_files` files, whatever the path filter,
+    /// are left out too.
+    pub fn new(
+        skip_merges: bool,
+        excluded_authors: &[String],
+        excluded_message: Option<&str>,
+        max_files: Option<usize>,
+    ) -> Result<Self> {
+        let excluded_authors = match excluded_authors.is_empty() {
+            true => None,
+            false => Some(RegexSet::new(excluded_authors.iter().map(|pattern| {
+                let literals: Vec<_> = pattern.split('*').map(regex::escape).collect();
+                format!("^{}$", literals.join(".*"))
+            }))?),
+        };
+        Ok(CommitFilter {
+            skip_merges,
+            excluded_authors,
+            excluded_message: excluded_message.map(Regex::new).transpose()?,
+            max_files,
+        })
+    }
+
+    /// Whether the commit is counted. Commits whose backend doesn't tell their parents aren't taken for merges.
+    pub fn is_included(&self, commit: &CommitChanges) -> bool {
+        let is_skipped_merge = self.skip_merges
+            && commit
+                .num_parents
+                .is_some_and(|num_parents| num_parents > 1);
+        let is_excluded_author = match (&self.excluded_authors, &commit.author) {
+            (Some(excluded_authors), Some(author)) => excluded_authors.is_match(author),
+            _ => false,
+        };
+        let is_excluded_message = self
+            .excluded_message
+            .as_ref()
+            .is_some_and(|excluded_message| excluded_message.is_match(&commit.message));
+        let is_too_large = self
+            .max_files
+            .is_some_and(|max_files| commit.files.len() > max_files);
+        !(is_skipped_merge || is_excluded_author || is_excluded_message || is_too_large)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::history::FileChange;
+
+    #[test]
+    fn filter_paths_with_globs_and_an_ignore_file() {
+        let ignore_file = "# Generated code\nsrc/generated/\n*.lock\n!keep.lock\n";
+        let path_filter = PathFilter::new(
+            &["src/**".into(), "*.lock".into()],
+            &["CHANGELOG.md".into()],
+            Some(ignore_file),
+        )
+        .unwrap();
+
+        assert!(path_filter.is_included("src/lib.rs"));
+        assert!(path_filter.is_included("src/history/mod.rs"));
+        assert!(path_filter.is_included("keep.lock"));
+        assert!(!path_filter.is_included("README.md"));
+        assert!(!path_filter.is_included("Cargo.lock"));
+        assert!(!path_filter.is_included("src/generated/protos.rs"));
+        assert!(!path_filter.is_included("CHANGELOG.md"));
+        assert!(PathFilter::default().is_included("Cargo.lock"));
+    }
+
+    #[test]
+    fn filter_merges_bots_messages_and_large_commits() {
+        let commit_filter = CommitFilter::new(
+            true,
+            &["*[bot]".into(), "Release Bot".into()],
+            Some("^(chore|style):"),
+            Some(2),
+        )
+        .unwrap();
+        let commit = |author: &str, message: &str, num_parents, num_files| CommitChanges {
+            author: Some(author.into()),
+            message: message.into(),
+            num_parents,
+            files: vec![FileChange::default(); num_files],
+            ..Default::default()
+        };
+
+        assert!(commit_filter.is_included(&commit("Ada", "Fix the parser", Some(1), 2)));
+        assert!(commit_filter.is_included(&commit("Ada", "Fix the parser", None, 1)));
+        assert!(commit_filter.is_included(&commit("bot", "Fix the parser", Some(1), 1)));
+        assert!(!commit_filter.is_included(&commit("Ada", "Merge branch 'main'", Some(2), 1)));
+        assert!(!commit_filter.is_included(&commit("dependabot[bot]", "Bump serde", Some(1), 1)));
+        assert!(!commit_filter.is_included(&commit("Release Bot", "v1.2.0", Some(1), 1)));
+        assert!(!commit_filter.is_included(&commit("Ada", "style: cargo fmt", Some(1), 1)));
+        assert!(!commit_filter.is_included(&commit("Ada", "Rename the modules", Some(1), 3)));
+        assert!(CommitFilter::default().is_included(&commit("Ada", "Merge", Some(2), 300)));
+    }
+}
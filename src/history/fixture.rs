@@ -0,0 +1,355 @@
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method, Request, Response, Server, StatusCode};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::convert::Infallible;
+use std::future::Future;
+use std::net::TcpListener;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use tokio::task::JoinHandle;
+
+const FIXTURE_FILENAME: &str = "responses.json";
+
+/// Headers that only make sense for a single connection, so they aren't forwarded nor recorded.
+const CONNECTION_HEADERS: [&str; 6] = [
+    "host",
+    "connection",
+    "content-length",
+    "transfer-encoding",
+    "accept-encoding",
+    "content-encoding",
+];
+
+/// An HTTP response of the forge, as it's replayed.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct RecordedResponse {
+    status: u16,
+    headers: BTreeMap<String, String>,
+    body: String,
+}
+
+impl RecordedResponse {
+    fn to_response(&self) -> Result<Response<Body>> {
+        let mut response = Response::builder().status(self.status);
+        for (name, value) in &self.headers {
+            response = response.header(name, value);
+        }
+        Ok(response.body(Body::from(self.body.clone()))?)
+    }
+}
+
+/// What a forge answered to an analysis, enough to run it again offline.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+struct Fixture {
+    /// When the responses were recorded. Replaying them resolves the relative bounds of the window from it, so the
+    /// requests are the same as the recorded ones
+    recorded_at: Option<DateTime<Utc>>,
+    /// By request: its method, path with the query string and body, if any
+    responses: BTreeMap<String, RecordedResponse>,
+}
+
+fn fixture_path(directory: &Path) -> PathBuf {
+    directory.join(FIXTURE_FILENAME)
+}
+
+fn request_key(method: &Method, path_and_query: &str, body: &[u8]) -> String {
+    match body.is_empty() {
+        true => format!("{method} {path_and_query}"),
+        false => format!(
+            "{method} {path_and_query} {}",
+            String::from_utf8_lossy(body)
+        ),
+    }
+}
+
+/// The forge responses of a `ForgeFixture`. Clones share them, so they can be saved once the analysis is done with
+/// the forge.
+#[derive(Debug, Clone, Default)]
+pub struct RecordedResponses(Arc<Mutex<Fixture>>);
+
+impl RecordedResponses {
+    /// No responses yet, recorded at `recorded_at`.
+    pub fn new(recorded_at: DateTime<Utc>) -> Self {
+        RecordedResponses(Arc::new(Mutex::new(Fixture {
+            recorded_at: Some(recorded_at),
+            ..Default::default()
+        })))
+    }
+
+    /// The responses saved in `directory`.
+    pub fn open(directory: &Path) -> Result<Self> {
+        let path = fixture_path(directory);
+        let json = std::fs::read(&path)
+            .with_context(|| format!("No recorded responses at {}", path.display()))?;
+        Ok(RecordedResponses(Arc::new(Mutex::new(
+            serde_json::from_slice(&json)?,
+        ))))
+    }
+
+    /// When the responses were recorded, if known.
+    pub fn recorded_at(&self) -> Option<DateTime<Utc>> {
+        self.0.lock().unwrap().recorded_at
+    }
+
+    /// Saves the responses in `directory`, to be replayed by `ForgeFixture::replay`.
+    pub fn save(&self, directory: &Path) -> Result<()> {
+        let fixture = self.0.lock().unwrap().clone();
+        std::fs::create_dir_all(directory)?;
+        std::fs::write(
+            fixture_path(directory),
+            serde_json::to_vec_pretty(&fixture)?,
+        )?;
+        Ok(())
+    }
+
+    fn record(&self, key: String, response: RecordedResponse) {
+        // Retried requests keep their last response
+        self.0.lock().unwrap().responses.insert(key, response);
+    }
+
+    fn response(&self, key: &str) -> Option<RecordedResponse> {
+        self.0.lock().unwrap().responses.get(key).cloned()
+    }
+}
+
+/// A local HTTP server standing in for a forge. The forge providers given its `base_url` send it their requests, and
+/// it either forwards them to the forge, recording the responses, or replays the recorded ones offline. So the
+/// replayed responses go through the same providers as the live ones.
+pub struct ForgeFixture {
+    base_url: String,
+    server: JoinHandle<()>,
+}
+
+impl ForgeFixture {
+    /// Forwards every request to the forge at `forge_url`, and records its responses in `responses`.
+    pub fn record(forge_url: &str, responses: RecordedResponses) -> Result<Self> {
+        let forge_url = forge_url.trim_end_matches('/').to_string();
+        let client = reqwest::Client::new();
+        ForgeFixture::serve(move |request| {
+            let (client, forge_url, responses) =
+                (client.clone(), forge_url.clone(), responses.clone());
+            async move { forward(&client, &forge_url, &responses, request).await }
+        })
+    }
+
+    /// Answers the requests with the `responses` recorded for them. Anything that wasn't recorded is not found, like
+    /// the forge answers when asked for something that doesn't exist.
+    pub fn replay(responses: RecordedResponses) -> Result<Self> {
+        ForgeFixture::serve(move |request| {
+            let responses = responses.clone();
+            async move {
+                let (parts, body) = request.into_parts();
+                let body = hyper::body::to_bytes(body).await?;
+                let key = request_key(&parts.method, &path_and_query(&parts.uri), &body);
+                match responses.response(&key) {
+                    Some(response) => response.to_response(),
+                    None => Ok(Response::builder()
+                        .status(StatusCode::NOT_FOUND)
+                        .body(Body::from(format!("No recorded response for {key}")))?),
+                }
+            }
+        })
+    }
+
+    /// Where to send the forge requests instead of the forge.
+    pub fn base_url(&self) -> &str {
+        &self.base_url
+    }
+
+    fn serve<F, Fut>(handle: F) -> Result<Self>
+    where
+        F: Fn(Request<Body>) -> Fut + Clone + Send + Sync + 'static,
+        Fut: Future<Output = Result<Response<Body>>> + Send + 'static,
+    {
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        listener.set_nonblocking(true)?;
+        let base_url = format!("http://{}", listener.local_addr()?);
+        let make_service = make_service_fn(move |_| {
+            let handle = handle.clone();
+            async move {
+                Ok::<_, Infallible>(service_fn(move |request| {
+                    let handle = handle.clone();
+                    async move {
+                        Ok::<_, Infallible>(handle(request).await.unwrap_or_else(|error| {
+                            let mut response = Response::new(Body::from(format!("{error:#}")));
+                            *response.status_mut() = StatusCode::BAD_GATEWAY;
+                            response
+                        }))
+                    }
+                }))
+            }
+        });
+        let server = Server::from_tcp(listener)?.serve(make_service);
+        let server = tokio::spawn(async move {
+            if let Err(error) = server.await {
+                log::warn!("The forge fixture server stopped: {error}");
+            }
+        });
+        Ok(ForgeFixture { base_url, server })
+    }
+}
+
+impl Drop for ForgeFixture {
+    fn drop(&mut self) {
+        self.server.abort();
+    }
+}
+
+fn path_and_query(uri: &hyper::Uri) -> String {
+    uri.path_and_query()
+        .map(|path_and_query| path_and_query.to_string())
+        .unwrap_or_else(|| "/".to_string())
+}
+
+/// Sends `request` to the forge and records its response.
+async fn forward(
+    client: &reqwest::Client,
+    forge_url: &str,
+    responses: &RecordedResponses,
+    request: Request<Body>,
+) -> Result<Response<Body>> {
+    let (parts, body) = request.into_parts();
+    let body = hyper::body::to_bytes(body).await?;
+    let path_and_query = path_and_query(&parts.uri);
+
+    let mut forwarded = client
+        .request(parts.method.clone(), format!("{forge_url}{path_and_query}"))
+        .body(body.clone());
+    for (name, value) in &parts.headers {
+        if !CONNECTION_HEADERS.contains(&name.as_str()) {
+            forwarded = forwarded.header(name, value);
+        }
+    }
+    let response = forwarded.send().await?;
+
+    let recorded = RecordedResponse {
+        status: response.status().as_u16(),
+        headers: response
+            .headers()
+            .iter()
+            .filter(|(name, _)| !CONNECTION_HEADERS.contains(&name.as_str()))
+            .filter_map(|(name, value)| Some((name.to_string(), value.to_str().ok()?.to_string())))
+            .collect(),
+        body: response.text().await?,
+    };
+    responses.record(
+        request_key(&parts.method, &path_and_query, &body),
+        recorded.clone(),
+    );
+    recorded.to_response()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::history::{ChangeHistoryProvider, GitLabHistory, HistoryQuery};
+    use chrono::TimeZone;
+    use futures_util::StreamExt;
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    fn fixture_directory() -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/data/gitlab-fixture")
+    }
+
+    fn since() -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2023, 1, 1, 0, 0, 0).unwrap()
+    }
+
+    async fn mine(history: &GitLabHistory) -> Vec<(String, Vec<String>)> {
+        history
+            .commits(&HistoryQuery::new(since(), None))
+            .await
+            .unwrap()
+            .map(|commit| {
+                let commit = commit.unwrap();
+                let filenames = commit.files.into_iter().map(|file| file.filename);
+                (commit.sha, filenames.collect())
+            })
+            .collect()
+            .await
+    }
+
+    #[tokio::test]
+    async fn replay_a_checked_in_fixture_through_the_forge_provider() {
+        let responses = RecordedResponses::open(&fixture_directory()).unwrap();
+        let fixture = ForgeFixture::replay(responses).unwrap();
+        let history = GitLabHistory::new(fixture.base_url(), "group/project", "token");
+
+        assert_eq!(
+            vec![
+                ("c2".to_string(), vec!["src/lib.rs".to_string()]),
+                (
+                    "c1".to_string(),
+                    vec!["src/lib.rs".to_string(), "README.md".to_string()]
+                ),
+            ],
+            mine(&history).await
+        );
+        assert!(history.file_contents("src/lib.rs", None).await.is_err());
+    }
+
+    async fn start_forge() -> MockServer {
+        let forge = MockServer::start().await;
+        let commits = r#"[
+            {"id": "c2", "author_name": "Ada", "authored_date": "2023-01-03T00:00:00Z", "message": "Fix", "parent_ids": ["c1"]},
+            {"id": "c1", "author_name": "Ada", "authored_date": "2023-01-02T00:00:00Z", "message": "Start", "parent_ids": []}
+        ]"#;
+        Mock::given(method("GET"))
+            .and(path("/api/v4/projects/group%2Fproject/repository/commits"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .insert_header("x-next-page", "")
+                    .set_body_raw(commits, "application/json"),
+            )
+            .mount(&forge)
+            .await;
+        for (sha, diffs) in [
+            (
+                "c2",
+                r#"[{"old_path": "src/lib.rs", "new_path": "src/lib.rs", "diff": "@@ -1 +1 @@\n-a\n+b\n"}]"#,
+            ),
+            (
+                "c1",
+                r#"[{"old_path": "src/lib.rs", "new_path": "src/lib.rs", "diff": "@@ -0,0 +1 @@\n+a\n"},
+                    {"old_path": "README.md", "new_path": "README.md", "diff": "@@ -0,0 +1 @@\n+Hi\n"}]"#,
+            ),
+        ] {
+            Mock::given(method("GET"))
+                .and(path(format!(
+                    "/api/v4/projects/group%2Fproject/repository/commits/{sha}/diff"
+                )))
+                .respond_with(ResponseTemplate::new(200).set_body_raw(diffs, "application/json"))
+                .mount(&forge)
+                .await;
+        }
+        forge
+    }
+
+    #[tokio::test]
+    async fn replay_the_recorded_responses() {
+        let forge = start_forge().await;
+        let responses = RecordedResponses::new(since());
+        let recording = ForgeFixture::record(&forge.uri(), responses.clone()).unwrap();
+        let recorded = mine(&GitLabHistory::new(
+            recording.base_url(),
+            "group/project",
+            "token",
+        ))
+        .await;
+        let directory = tempfile::tempdir().unwrap();
+        responses.save(directory.path()).unwrap();
+        drop(forge);
+
+        let responses = RecordedResponses::open(directory.path()).unwrap();
+        let replaying = ForgeFixture::replay(responses.clone()).unwrap();
+        let history = GitLabHistory::new(replaying.base_url(), "group/project", "token");
+
+        assert_eq!(2, recorded.len());
+        assert_eq!(recorded, mine(&history).await);
+        assert_eq!(Some(since()), responses.recorded_at());
+    }
+}
@@ -0,0 +1,455 @@
+use super::{
+    parse_changed_lines, report_progress, ChangeHistoryProvider, CommitChanges, CommitStream,
+    FileChange, HistoryQuery,
+};
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
+use futures::{future, stream};
+use futures_util::StreamExt;
+use std::path::PathBuf;
+use std::process::Stdio;
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncReadExt, BufReader};
+use tokio::process::Command;
+
+// Separates commits in the `git log` output, so we can tell commit headers from file names
+const COMMIT_SEPARATOR: char = '\x1e';
+// Starts the patch of every file, after the --raw and --numstat lines of the commit
+const PATCH_HEADER: &str = "\ndiff --git ";
+
+/// Change history read from a local clone of a repository, using the `git` command line.
+pub struct LocalGitHistory {
+    repo_path: PathBuf,
+}
+
+impl LocalGitHistory {
+    pub fn new(repo_path: PathBuf) -> Self {
+        LocalGitHistory { repo_path }
+    }
+
+    fn command(&self, args: &[String]) -> Command {
+        let mut command = Command::new("git");
+        command.arg("-C").arg(&self.repo_path).args(args);
+        command
+    }
+
+    fn failure(&self, args: &[String], stderr: &[u8]) -> anyhow::Error {
+        anyhow!(
+            "git {} failed on {}: {}",
+            args.first().map_or("", |command| command.as_str()),
+            self.repo_path.to_string_lossy(),
+            String::from_utf8_lossy(stderr)
+        )
+    }
+
+    /// Runs a git command on the repository and returns its standard output.
+    async fn git(&self, args: &[String]) -> Result<String> {
+        let output = self.command(args).output().await?;
+
+        if !output.status.success() {
+            return Err(self.failure(args, &output.stderr));
+        }
+
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    }
+
+    /// Runs `git log` on the repository and parses every commit as soon as git prints it, instead of keeping the
+    /// whole history in memory. The stream ends with an error if git fails.
+    fn log(&self, args: Vec<String>) -> Result<CommitStream<'_>> {
+        let mut child = self
+            .command(&args)
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()?;
+        let stdout = child.stdout.take().expect("The standard output is piped");
+
+        let exit = stream::once(async move {
+            let mut stderr = Vec::new();
+            if let Some(mut child_stderr) = child.stderr.take() {
+                child_stderr.read_to_end(&mut stderr).await.ok();
+            }
+            match child.wait().await {
+                Ok(status) if status.success() => None,
+                Ok(_) => Some(Err(self.failure(&args, &stderr))),
+                Err(error) => Some(Err(error.into())),
+            }
+        })
+        .filter_map(future::ready);
+        Ok(parse_git_log(BufReader::new(stdout)).chain(exit).boxed())
+    }
+}
+
+#[async_trait::async_trait]
+impl ChangeHistoryProvider for LocalGitHistory {
+    async fn commits<'a>(&'a self, query: &HistoryQuery) -> Result<CommitStream<'a>> {
+        let mut args = vec![
+            "log".to_string(),
+            format!("--since={}", query.since.to_rfc3339()),
+            format!("--format={COMMIT_SEPARATOR}%H%x09%aI%x09%an%x09%P%x09%s"),
+            // --raw tells the status and paths of every file and --numstat its lines changed, in the same order
+            "--raw".to_string(),
+            "--numstat".to_string(),
+            // Detect renames, so we can follow the history of moved files
+            "-M".to_string(),
+        ];
+        if query.changed_lines {
+            // The hunks of the patch of every file, with no context lines, tell the lines it changed
+            args.extend(["--patch".to_string(), "--unified=0".to_string()]);
+        }
+        if let Some(until) = query.until {
+            args.push(format!("--until={}", until.to_rfc3339()));
+        }
+        if let Some(git_ref) = &query.git_ref {
+            args.push(git_ref.clone());
+        }
+        args.push("--".to_string());
+
+        Ok(report_progress(self.log(args)?, query))
+    }
+
+    async fn last_commit_before(
+        &self,
+        git_ref: Option<&str>,
+        date: DateTime<Utc>,
+    ) -> Result<Option<String>> {
+        let args = [
+            "rev-list".to_string(),
+            "-1".to_string(),
+            format!("--before={}", date.to_rfc3339()),
+            git_ref.unwrap_or("HEAD").to_string(),
+            "--".to_string(),
+        ];
+        let sha = self.git(&args).await?;
+        Ok(Some(sha.trim().to_string()).filter(|sha| !sha.is_empty()))
+    }
+
+    async fn commit_date(&self, git_ref: &str) -> Result<DateTime<Utc>> {
+        let args = [
+            "log".to_string(),
+            "-1".to_string(),
+            "--format=%cI".to_string(),
+            git_ref.to_string(),
+            "--".to_string(),
+        ];
+        let date = DateTime::parse_from_rfc3339(self.git(&args).await?.trim())?;
+        Ok(date.with_timezone(&Utc))
+    }
+
+    async fn file_contents(&self, path: &str, git_ref: Option<&str>) -> Result<String> {
+        match git_ref {
+            Some(git_ref) => {
+                self.git(&["show".to_string(), format!("{git_ref}:{path}")])
+                    .await
+            }
+            None => Ok(tokio::fs::read_to_string(self.repo_path.join(path)).await?),
+        }
+    }
+}
+
+/// Parses the commits of a `git log` output as they come. Lines that aren't valid UTF-8, like those of patches of
+/// files in other encodings, are read lossily.
+fn parse_git_log<'a, R>(log: R) -> CommitStream<'a>
+where
+    R: AsyncBufRead + Unpin + Send + 'a,
+{
+    stream::try_unfold((log, String::new()), |(mut log, mut commit)| async move {
+        let mut line = Vec::new();
+        loop {
+            line.clear();
+            let end_of_log = log.read_until(b'\n', &mut line).await? == 0;
+            let line = String::from_utf8_lossy(&line);
+            if !end_of_log && !line.starts_with(COMMIT_SEPARATOR) {
+                commit.push_str(&line);
+                continue;
+            }
+            // The commit ends where the next one starts
+            let parsed_commit = std::mem::replace(&mut commit, line.into_owned());
+            match (parsed_commit.trim().is_empty(), end_of_log) {
+                (false, _) => return Ok(Some((parse_commit(&parsed_commit), (log, commit)))),
+                (true, true) => return Ok(None),
+                (true, false) => continue,
+            }
+        }
+    })
+    .boxed()
+}
+
+fn parse_commit(commit: &str) -> CommitChanges {
+    let commit = commit.trim_start_matches(COMMIT_SEPARATOR);
+    let (summary, patches) = commit.split_once(PATCH_HEADER).unwrap_or((commit, ""));
+    let mut lines = summary.lines();
+    let mut header = lines.next().unwrap_or_default().splitn(5, '\t');
+    let sha = header.next().unwrap_or_default();
+    let date = header
+        .next()
+        .and_then(|date| DateTime::parse_from_rfc3339(date).ok())
+        .map(|date| date.with_timezone(&Utc));
+    let author = header.next().unwrap_or_default();
+    let parents = header.next().unwrap_or_default();
+    let message = header.next().unwrap_or_default();
+    let (raw_lines, numstat_lines): (Vec<_>, Vec<_>) = lines
+        .filter(|line| !line.is_empty())
+        .partition(|line| line.starts_with(':'));
+    // Every file has a patch, even if it has no hunks, like binaries or pure renames
+    let mut patches = patches.split(PATCH_HEADER);
+    let files = raw_lines
+        .into_iter()
+        .filter_map(parse_file_status)
+        .zip(numstat_lines.into_iter().map(parse_numstat))
+        .map(|(file_change, (additions, deletions))| FileChange {
+            additions,
+            deletions,
+            changed_lines: patches.next().map(parse_changed_lines).unwrap_or_default(),
+            ..file_change
+        })
+        .collect();
+    CommitChanges {
+        sha: sha.to_string(),
+        author: Some(author.to_string()),
+        date,
+        message: message.to_string(),
+        files,
+        num_parents: Some(parents.split_whitespace().count() as u32),
+    }
+}
+
+/// Parses a `--raw` line: modes, blobs and status, then the path, or the old and new paths for renames and copies.
+fn parse_file_status(line: &str) -> Option<FileChange> {
+    let mut fields = line.split('\t');
+    let status = fields.next()?.rsplit(' ').next()?;
+    let path = fields.next()?;
+    Some(match fields.next() {
+        Some(new_path) => FileChange {
+            filename: new_path.to_string(),
+            previous_filename: status.starts_with('R').then(|| path.to_string()),
+            ..Default::default()
+        },
+        None => FileChange {
+            filename: path.to_string(),
+            ..Default::default()
+        },
+    })
+}
+
+/// Parses the added and deleted lines of a `--numstat` line. Binary files show `-` instead, we count them as 0.
+fn parse_numstat(line: &str) -> (u32, u32) {
+    let mut fields = line.split('\t').map(|count| count.parse().unwrap_or(0));
+    (fields.next().unwrap_or(0), fields.next().unwrap_or(0))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use chrono::TimeZone;
+    use futures_util::TryStreamExt;
+    use std::path::Path;
+
+    fn git(repo_path: &Path, args: &[&str], date: &str) {
+        let status = std::process::Command::new("git")
+            .arg("-C")
+            .arg(repo_path)
+            .args([
+                "-c",
+                "user.name=Radar",
+                "-c",
+                "user.email=radar@example.com",
+            ])
+            .args(args)
+            .env("GIT_AUTHOR_DATE", date)
+            .env("GIT_COMMITTER_DATE", date)
+            .status()
+            .expect("git is required to run this test");
+        assert!(status.success(), "git {args:?} failed");
+    }
+
+    fn commit_file(repo_path: &Path, filename: &str, date: &str) {
+        std::fs::write(repo_path.join(filename), date).unwrap();
+        git(repo_path, &["add", filename], date);
+        git(repo_path, &["commit", "-q", "-m", filename], date);
+    }
+
+    #[tokio::test]
+    async fn parse_commits_and_changed_files_from_git_log() {
+        let log = "\x1eaaaa\t2023-01-02T10:00:00+02:00\tAda\tbbbb cccc\tFix the parser\n\n\
+            :100644 100644 1111111 2222222 M\tsrc/lib.rs\n\
+            :100644 100644 3333333 4444444 R087\tREADME\tREADME.md\n\
+            10\t2\tsrc/lib.rs\n\
+            1\t1\tREADME => README.md\n\
+            \n\
+            diff --git a/src/lib.rs b/src/lib.rs\n\
+            @@ -1,2 +1,10 @@\n\
+            :not a file\n\
+            diff --git a/README b/README.md\n\
+            similarity index 87%\n\
+            @@ -3 +3 @@\n\
+            \x1ebbbb\t2023-01-01T00:00:00Z\tGrace\t\tAdd the parser\tfor real\n\n\
+            :000000 100644 0000000 5555555 A\tsrc/lib.rs\n\
+            -\t-\tsrc/lib.rs\n";
+
+        let expected = vec![
+            CommitChanges {
+                sha: "aaaa".into(),
+                author: Some("Ada".into()),
+                date: Some(Utc.with_ymd_and_hms(2023, 1, 2, 8, 0, 0).unwrap()),
+                message: "Fix the parser".into(),
+                files: vec![
+                    FileChange {
+                        filename: "src/lib.rs".into(),
+                        previous_filename: None,
+                        additions: 10,
+                        deletions: 2,
+                        changed_lines: vec![1..=10],
+                    },
+                    FileChange {
+                        filename: "README.md".into(),
+                        previous_filename: Some("README".into()),
+                        additions: 1,
+                        deletions: 1,
+                        changed_lines: vec![3..=3],
+                    },
+                ],
+                num_parents: Some(2),
+            },
+            CommitChanges {
+                sha: "bbbb".into(),
+                author: Some("Grace".into()),
+                date: Some(Utc.with_ymd_and_hms(2023, 1, 1, 0, 0, 0).unwrap()),
+                message: "Add the parser\tfor real".into(),
+                files: vec![FileChange {
+                    filename: "src/lib.rs".into(),
+                    ..Default::default()
+                }],
+                num_parents: Some(0),
+            },
+        ];
+
+        let commits: Vec<_> = parse_git_log(log.as_bytes()).try_collect().await.unwrap();
+        assert_eq!(expected, commits);
+    }
+
+    #[tokio::test]
+    async fn read_the_changed_lines_only_when_asked_for() {
+        let repo = tempfile::tempdir().unwrap();
+        let date = "2023-01-01T00:00:00Z";
+        git(repo.path(), &["init", "-q"], date);
+        commit_file(repo.path(), "lib.rs", date);
+        let local_history = LocalGitHistory::new(repo.path().into());
+        let since = Utc.with_ymd_and_hms(2022, 1, 1, 0, 0, 0).unwrap();
+
+        let mut changed_lines = vec![];
+        for query in [
+            HistoryQuery::new(since, None),
+            HistoryQuery {
+                changed_lines: true,
+                ..HistoryQuery::new(since, None)
+            },
+        ] {
+            let commits: Vec<CommitChanges> = local_history
+                .commits(&query)
+                .await
+                .unwrap()
+                .try_collect()
+                .await
+                .unwrap();
+            changed_lines.push(commits[0].files[0].changed_lines.clone());
+        }
+
+        assert_eq!(vec![vec![], vec![1..=1]], changed_lines);
+    }
+
+    #[tokio::test]
+    async fn fail_the_stream_when_git_fails() {
+        let repo = tempfile::tempdir().unwrap();
+        let local_history = LocalGitHistory::new(repo.path().into());
+
+        let commits: Result<Vec<CommitChanges>> = local_history
+            .commits(&HistoryQuery::default())
+            .await
+            .unwrap()
+            .try_collect()
+            .await;
+
+        assert!(commits.is_err());
+    }
+
+    #[tokio::test]
+    async fn list_only_the_commits_in_the_analysis_window() {
+        let repo = tempfile::tempdir().unwrap();
+        git(repo.path(), &["init", "-q"], "2023-01-01T00:00:00Z");
+        commit_file(repo.path(), "old.rs", "2023-01-01T00:00:00Z");
+        commit_file(repo.path(), "tagged.rs", "2023-02-01T00:00:00Z");
+        git(repo.path(), &["tag", "v1.0.0"], "2023-02-01T00:00:00Z");
+        commit_file(repo.path(), "new.rs", "2023-03-01T00:00:00Z");
+        commit_file(repo.path(), "newest.rs", "2023-04-01T00:00:00Z");
+        let local_history = LocalGitHistory::new(repo.path().into());
+
+        let since = local_history.commit_date("v1.0.0").await.unwrap();
+        let before_the_tag = Utc.with_ymd_and_hms(2023, 1, 15, 0, 0, 0).unwrap();
+        let last_before_the_tag = local_history
+            .last_commit_before(None, before_the_tag)
+            .await
+            .unwrap();
+        let until = Utc.with_ymd_and_hms(2023, 3, 15, 0, 0, 0).unwrap();
+        let commits: Vec<CommitChanges> = local_history
+            .commits(&HistoryQuery::new(since, Some(until)))
+            .await
+            .unwrap()
+            .try_collect()
+            .await
+            .unwrap();
+
+        let changed_files: Vec<_> = commits
+            .iter()
+            .flat_map(|commit| commit.files.iter().map(|file| file.filename.as_str()))
+            .collect();
+        assert_eq!(Utc.with_ymd_and_hms(2023, 2, 1, 0, 0, 0).unwrap(), since);
+        assert_eq!(vec!["new.rs", "tagged.rs"], changed_files);
+        assert_eq!(
+            local_history
+                .git(&["rev-parse".into(), "v1.0.0~1".into()])
+                .await
+                .unwrap()
+                .trim(),
+            last_before_the_tag.unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn list_the_commits_and_read_the_files_of_a_branch() {
+        let repo = tempfile::tempdir().unwrap();
+        let date = "2023-01-01T00:00:00Z";
+        git(repo.path(), &["init", "-q", "-b", "main"], date);
+        commit_file(repo.path(), "lib.rs", date);
+        git(repo.path(), &["checkout", "-q", "-b", "release"], date);
+        std::fs::write(repo.path().join("lib.rs"), "fn release() {}").unwrap();
+        git(repo.path(), &["commit", "-q", "-am", "release"], date);
+        commit_file(repo.path(), "release.rs", date);
+        git(repo.path(), &["checkout", "-q", "main"], date);
+        let local_history = LocalGitHistory::new(repo.path().into());
+
+        let query = HistoryQuery {
+            git_ref: Some("release".into()),
+            ..HistoryQuery::new(Utc.with_ymd_and_hms(2022, 1, 1, 0, 0, 0).unwrap(), None)
+        };
+        let commits: Vec<CommitChanges> = local_history
+            .commits(&query)
+            .await
+            .unwrap()
+            .try_collect()
+            .await
+            .unwrap();
+
+        assert_eq!(3, commits.len());
+        assert_eq!(
+            "fn release() {}",
+            local_history
+                .file_contents("lib.rs", Some("release"))
+                .await
+                .unwrap()
+        );
+        assert_eq!(
+            date,
+            local_history.file_contents("lib.rs", None).await.unwrap()
+        );
+    }
+}
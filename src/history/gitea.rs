@@ -0,0 +1,249 @@
+use super::{
+    fetch_commits, ChangeHistoryProvider, CommitChanges, CommitStream, FileChange, ForgeOptions,
+    HistoryQuery, PagedApi, WithForgeOptions,
+};
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use futures::future;
+use futures_util::{StreamExt, TryStreamExt};
+use serde::de::{DeserializeOwned, IgnoredAny};
+use serde::Deserialize;
+
+const PAGE_SIZE: u64 = 50;
+
+/// Change history of a repository hosted on Gitea or Forgejo, which share the same REST API.
+///
+/// Their API doesn't tell the previous path of renamed files nor the lines changed per file, so renames can't be
+/// followed and rankings by lines changed don't work.
+pub struct GiteaHistory {
+    client: reqwest::Client,
+    base_url: String,
+    owner: String,
+    repo: String,
+    token: String,
+    options: ForgeOptions,
+}
+
+#[derive(Deserialize)]
+struct GiteaCommit {
+    sha: String,
+    commit: Option<GiteaGitCommit>,
+}
+
+#[derive(Deserialize)]
+struct GiteaCommitDetails {
+    sha: String,
+    commit: Option<GiteaGitCommit>,
+    #[serde(default)]
+    files: Vec<GiteaChangedFile>,
+    parents: Option<Vec<IgnoredAny>>,
+}
+
+#[derive(Deserialize)]
+struct GiteaChangedFile {
+    filename: String,
+}
+
+#[derive(Deserialize)]
+struct GiteaCommitDate {
+    commit: GiteaGitCommit,
+}
+
+#[derive(Deserialize)]
+struct GiteaGitCommit {
+    author: Option<GiteaGitUser>,
+    committer: GiteaGitUser,
+    #[serde(default)]
+    message: String,
+}
+
+#[derive(Deserialize)]
+struct GiteaGitUser {
+    name: Option<String>,
+    date: DateTime<Utc>,
+}
+
+impl From<GiteaCommitDetails> for CommitChanges {
+    fn from(commit: GiteaCommitDetails) -> Self {
+        let (author, message) = match commit.commit {
+            Some(git_commit) => (git_commit.author, git_commit.message),
+            None => (None, String::new()),
+        };
+        CommitChanges {
+            sha: commit.sha,
+            author: author.as_ref().and_then(|author| author.name.clone()),
+            date: author.map(|author| author.date),
+            message,
+            files: commit
+                .files
+                .into_iter()
+                .map(|file| FileChange {
+                    filename: file.filename,
+                    ..Default::default()
+                })
+                .collect(),
+            num_parents: commit.parents.map(|parents| parents.len() as u32),
+        }
+    }
+}
+
+impl GiteaHistory {
+    pub fn new(base_url: &str, owner: &str, repo: &str, token: &str) -> Self {
+        GiteaHistory {
+            client: reqwest::Client::new(),
+            base_url: base_url.trim_end_matches('/').to_string(),
+            owner: owner.to_string(),
+            repo: repo.to_string(),
+            token: token.to_string(),
+            options: ForgeOptions::default(),
+        }
+    }
+
+    fn repo_url(&self) -> String {
+        format!(
+            "{}/api/v1/repos/{}/{}",
+            self.base_url, self.owner, self.repo
+        )
+    }
+
+    fn get(&self, url: &str) -> reqwest::RequestBuilder {
+        self.client
+            .get(url)
+            .header("Authorization", format!("token {}", self.token))
+    }
+
+    async fn get_commit_changes(&self, sha: String) -> Result<CommitChanges> {
+        let commit_url = format!("{}/git/commits/{}", self.repo_url(), sha);
+        let commit: GiteaCommitDetails = self
+            .get(&commit_url)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        Ok(commit.into())
+    }
+}
+
+#[async_trait::async_trait]
+impl PagedApi for GiteaHistory {
+    const FIRST_PAGE: u64 = 1;
+
+    async fn get_page<T: DeserializeOwned + Send>(
+        &self,
+        url: &str,
+        query: &[(&str, String)],
+        page: u64,
+    ) -> Result<(Vec<T>, Option<u64>)> {
+        let response = self
+            .get(url)
+            .query(query)
+            .query(&[("page", page), ("limit", PAGE_SIZE)])
+            .send()
+            .await?
+            .error_for_status()?;
+
+        let has_more = response
+            .headers()
+            .get("x-hasmore")
+            .is_some_and(|has_more| has_more == "true");
+        let next_page = has_more.then_some(page + 1);
+
+        Ok((response.json().await?, next_page))
+    }
+}
+
+impl WithForgeOptions for GiteaHistory {
+    fn forge_options(&mut self) -> &mut ForgeOptions {
+        &mut self.options
+    }
+}
+
+#[async_trait::async_trait]
+impl ChangeHistoryProvider for GiteaHistory {
+    async fn commits<'a>(&'a self, query: &HistoryQuery) -> Result<CommitStream<'a>> {
+        let commits_url = format!("{}/commits", self.repo_url());
+        let mut params = vec![
+            ("since", query.since.to_rfc3339()),
+            // We fetch the changed files of every commit afterwards, skip the expensive bits of the listing
+            ("stat", "false".to_string()),
+            ("verification", "false".to_string()),
+            ("files", "false".to_string()),
+        ];
+        if let Some(until) = query.until {
+            params.push(("until", until.to_rfc3339()));
+        }
+        if let Some(git_ref) = &query.git_ref {
+            params.push(("sha", git_ref.clone()));
+        }
+
+        // Older Gitea versions ignore `since` and `until`, so we check the window on our side too. Commits listed
+        // without their dates are kept
+        let window = query.clone();
+        let commits_stream = self
+            .list::<GiteaCommit>(commits_url, params)
+            .await?
+            .try_filter(move |commit| {
+                let author_date = commit
+                    .commit
+                    .as_ref()
+                    .and_then(|git_commit| git_commit.author.as_ref());
+                future::ready(author_date.is_none_or(|author| window.contains(author.date)))
+            });
+
+        Ok(fetch_commits(
+            commits_stream.boxed(),
+            query,
+            &self.options,
+            |commit| commit.sha.clone(),
+            move |commit| self.get_commit_changes(commit.sha),
+        ))
+    }
+
+    async fn commit_date(&self, git_ref: &str) -> Result<DateTime<Utc>> {
+        let commit: GiteaCommitDate = self
+            .get(&format!("{}/git/commits/{}", self.repo_url(), git_ref))
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        Ok(commit.commit.committer.date)
+    }
+
+    async fn last_commit_before(
+        &self,
+        git_ref: Option<&str>,
+        date: DateTime<Utc>,
+    ) -> Result<Option<String>> {
+        let mut params = vec![
+            ("until", date.to_rfc3339()),
+            ("stat", "false".to_string()),
+            ("verification", "false".to_string()),
+            ("files", "false".to_string()),
+        ];
+        if let Some(git_ref) = git_ref {
+            params.push(("sha", git_ref.to_string()));
+        }
+        // Older Gitea versions ignore `until`, so we may have to skip the newer commits ourselves
+        let mut commits = self
+            .list::<GiteaCommit>(format!("{}/commits", self.repo_url()), params)
+            .await?
+            .try_filter(move |commit| {
+                let committer = commit
+                    .commit
+                    .as_ref()
+                    .map(|git_commit| &git_commit.committer);
+                future::ready(committer.is_none_or(|committer| committer.date <= date))
+            });
+        Ok(commits.try_next().await?.map(|commit| commit.sha))
+    }
+
+    async fn file_contents(&self, path: &str, git_ref: Option<&str>) -> Result<String> {
+        let mut request = self.get(&format!("{}/raw/{}", self.repo_url(), path));
+        if let Some(git_ref) = git_ref {
+            request = request.query(&[("ref", git_ref)]);
+        }
+        Ok(request.send().await?.error_for_status()?.text().await?)
+    }
+}
@@ -0,0 +1,259 @@
+use super::rate_limit::{log_remaining_quota, retry_delay};
+use super::{
+    fetch_commits, parse_changed_lines, ChangeHistoryProvider, CommitChanges, CommitStream,
+    FileChange, ForgeOptions, HistoryQuery, PagedApi, WithForgeOptions,
+};
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
+use http::HeaderMap;
+use octocrab::models::issues::Issue;
+use octocrab::models::repos::{Content, DiffEntry, DiffEntryStatus, RepoCommit};
+use octocrab::Octocrab;
+use reqwest::Url;
+use serde::de::DeserializeOwned;
+
+pub const GITHUB_DEFAULT_BASE_URL: &str = "https://api.github.com";
+
+const PAGE_SIZE: u64 = 100;
+
+/// Change history of a repository hosted on GitHub (or GitHub Enterprise).
+pub struct GitHubHistory {
+    pub(super) octocrab: Octocrab,
+    pub(super) owner: String,
+    pub(super) repo: String,
+    pub(super) options: ForgeOptions,
+}
+
+impl GitHubHistory {
+    pub fn new(octocrab: Octocrab, owner: &str, repo: &str) -> Self {
+        GitHubHistory {
+            octocrab,
+            owner: owner.to_string(),
+            repo: repo.to_string(),
+            options: ForgeOptions::default(),
+        }
+    }
+
+    /// Route of the repository in the REST API.
+    pub(super) fn repo_route(&self) -> String {
+        format!("/repos/{}/{}", self.owner, self.repo)
+    }
+
+    /// GETs a route of the API, waiting and retrying while rate limited or on server errors.
+    pub(super) async fn get<R: DeserializeOwned>(&self, route: &str) -> Result<R> {
+        let (_, body) = self.send(route, None).await?;
+        Ok(serde_json::from_str(&body)?)
+    }
+
+    /// POSTs `body` to a route of the API, retrying like [`GitHubHistory::get`].
+    pub(super) async fn post<R: DeserializeOwned>(
+        &self,
+        route: &str,
+        body: &serde_json::Value,
+    ) -> Result<R> {
+        let (_, body) = self.send(route, Some(body)).await?;
+        Ok(serde_json::from_str(&body)?)
+    }
+
+    /// Sends a request to a route of the API, POSTing `body` if any and GETting otherwise, and returns the headers
+    /// and body of the response. Every request goes through here, to wait and retry while rate limited or on
+    /// server errors.
+    async fn send(
+        &self,
+        route: &str,
+        body: Option<&serde_json::Value>,
+    ) -> Result<(HeaderMap, String)> {
+        let mut attempt = 0;
+        loop {
+            let response = match body {
+                Some(body) => self.octocrab._post(route, Some(body)).await?,
+                None => self.octocrab._get(route).await?,
+            };
+            let header = |name: &str| {
+                response
+                    .headers()
+                    .get(name)
+                    .and_then(|value| value.to_str().ok())
+            };
+            log_remaining_quota("GitHub", header);
+            if let Some(delay) =
+                retry_delay(response.status().as_u16(), header, attempt, Utc::now())
+            {
+                log::warn!(
+                    "GitHub answered {} to {route}, retrying in {}s",
+                    response.status(),
+                    delay.as_secs()
+                );
+                tokio::time::sleep(delay).await;
+                attempt += 1;
+                continue;
+            }
+            let response = octocrab::map_github_error(response).await?;
+            let headers = response.headers().clone();
+            let body = self.octocrab.body_to_string(response).await?;
+            return Ok((headers, body));
+        }
+    }
+}
+
+/// `route` with the `query` parameters appended, URL encoded.
+fn with_query(route: &str, query: &[(&str, String)]) -> String {
+    // Only the query of this URL is used
+    let mut url = Url::parse("https://api.github.com").expect("Invalid GitHub API URL");
+    url.query_pairs_mut().extend_pairs(query);
+    format!("{route}?{}", url.query().unwrap_or_default())
+}
+
+#[async_trait::async_trait]
+impl PagedApi for GitHubHistory {
+    const FIRST_PAGE: u64 = 1;
+
+    async fn get_page<T: DeserializeOwned + Send>(
+        &self,
+        url: &str,
+        query: &[(&str, String)],
+        page: u64,
+    ) -> Result<(Vec<T>, Option<u64>)> {
+        let mut query = query.to_vec();
+        query.extend([
+            ("page", page.to_string()),
+            ("per_page", PAGE_SIZE.to_string()),
+        ]);
+        let (headers, body) = self.send(&with_query(url, &query), None).await?;
+
+        // GitHub links the next page, if any
+        let has_next_page = headers
+            .get("link")
+            .and_then(|link| link.to_str().ok())
+            .is_some_and(|link| link.contains("rel=\"next\""));
+        Ok((
+            serde_json::from_str(&body)?,
+            has_next_page.then_some(page + 1),
+        ))
+    }
+}
+
+impl From<DiffEntry> for FileChange {
+    fn from(diff_entry: DiffEntry) -> Self {
+        FileChange {
+            previous_filename: match diff_entry.status {
+                DiffEntryStatus::Renamed => diff_entry.previous_filename,
+                _ => None,
+            },
+            filename: diff_entry.filename,
+            changed_lines: diff_entry
+                .patch
+                .as_deref()
+                .map(parse_changed_lines)
+                .unwrap_or_default(),
+            additions: diff_entry.additions as u32,
+            deletions: diff_entry.deletions as u32,
+        }
+    }
+}
+
+impl From<RepoCommit> for CommitChanges {
+    fn from(repo_commit: RepoCommit) -> Self {
+        CommitChanges {
+            num_parents: Some(repo_commit.parents.len() as u32),
+            sha: repo_commit.sha,
+            author: repo_commit
+                .commit
+                .author
+                .as_ref()
+                .map(|author| author.user.name.clone()),
+            date: repo_commit.commit.author.and_then(|author| author.date),
+            message: repo_commit.commit.message,
+            files: repo_commit
+                .files
+                .unwrap_or_default()
+                .into_iter()
+                .map(FileChange::from)
+                .collect(),
+        }
+    }
+}
+
+impl WithForgeOptions for GitHubHistory {
+    fn forge_options(&mut self) -> &mut ForgeOptions {
+        &mut self.options
+    }
+}
+
+#[async_trait::async_trait]
+impl ChangeHistoryProvider for GitHubHistory {
+    async fn commits<'a>(&'a self, query: &HistoryQuery) -> Result<CommitStream<'a>> {
+        let mut params = vec![("since", query.since.to_rfc3339())];
+        if let Some(until) = query.until {
+            params.push(("until", until.to_rfc3339()));
+        }
+        if let Some(git_ref) = &query.git_ref {
+            params.push(("sha", git_ref.clone()));
+        }
+        let commits_url = format!("{}/commits", self.repo_route());
+        let commits_stream = self.list::<RepoCommit>(commits_url, params).await?;
+
+        // The commits listing doesn't include the changed files, so we need to fetch every commit. By its route, not
+        // the absolute URL of the listing, so it goes to the same base URL, like a recording or replaying fixture
+        Ok(fetch_commits(
+            commits_stream,
+            query,
+            &self.options,
+            |repo_commit| repo_commit.sha.clone(),
+            move |repo_commit| async move {
+                let commit_route = format!("{}/commits/{}", self.repo_route(), repo_commit.sha);
+                let repo_commit: RepoCommit = self.get(&commit_route).await?;
+                Ok(CommitChanges::from(repo_commit))
+            },
+        ))
+    }
+
+    async fn commit_date(&self, git_ref: &str) -> Result<DateTime<Utc>> {
+        let repo_commit: RepoCommit = self
+            .get(&format!("{}/commits/{git_ref}", self.repo_route()))
+            .await?;
+        repo_commit
+            .commit
+            .committer
+            .and_then(|committer| committer.date)
+            .ok_or_else(|| anyhow!("GitHub returned no commit date for {git_ref}"))
+    }
+
+    async fn last_commit_before(
+        &self,
+        git_ref: Option<&str>,
+        date: DateTime<Utc>,
+    ) -> Result<Option<String>> {
+        let mut params = vec![("until", date.to_rfc3339()), ("per_page", "1".to_string())];
+        if let Some(git_ref) = git_ref {
+            params.push(("sha", git_ref.to_string()));
+        }
+        let commits: Vec<RepoCommit> = self
+            .get(&with_query(
+                &format!("{}/commits", self.repo_route()),
+                &params,
+            ))
+            .await?;
+        Ok(commits.into_iter().next().map(|commit| commit.sha))
+    }
+
+    async fn issue_labels(&self, number: u64) -> Result<Vec<String>> {
+        // Pull requests are issues too, so this works for both
+        let issue: Issue = self
+            .get(&format!("{}/issues/{number}", self.repo_route()))
+            .await?;
+        Ok(issue.labels.into_iter().map(|label| label.name).collect())
+    }
+
+    async fn file_contents(&self, path: &str, git_ref: Option<&str>) -> Result<String> {
+        let params: Vec<_> = git_ref
+            .iter()
+            .map(|git_ref| ("ref", git_ref.to_string()))
+            .collect();
+        let content_route = format!("{}/contents/{path}", self.repo_route());
+        let content: Content = self.get(&with_query(&content_route, &params)).await?;
+        content
+            .decoded_content()
+            .ok_or_else(|| anyhow!("GitHub returned no contents for {path}"))
+    }
+}
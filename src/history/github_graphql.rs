@@ -0,0 +1,373 @@
+use super::github::GitHubHistory;
+use super::{
+    fetch_commits, paginate, ChangeHistoryProvider, CommitChanges, CommitStream, FileChange,
+    ForgeOptions, HistoryQuery,
+};
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
+use octocrab::models::repos::{DiffEntry, RepoCommit};
+use serde::Deserialize;
+use serde_json::json;
+
+const PAGE_SIZE: u32 = 100;
+
+const HISTORY_QUERY: &str = "
+query($owner: String!, $repo: String!, $ref: String!, $since: GitTimestamp, $until: GitTimestamp, $first: Int!, $cursor: String) {
+  repository(owner: $owner, name: $repo) {
+    object(expression: $ref) {
+      ... on Commit {
+        history(first: $first, since: $since, until: $until, after: $cursor) {
+          pageInfo { hasNextPage endCursor }
+          nodes {
+            oid
+            message
+            authoredDate
+            author { name }
+            changedFilesIfAvailable
+            additions
+            deletions
+            parents(first: 1) { totalCount nodes { oid } }
+            associatedPullRequests(first: 1) {
+              nodes {
+                mergeCommit { oid }
+                changedFiles
+                additions
+                deletions
+                files(first: 100) { nodes { path additions deletions changeType } }
+              }
+            }
+          }
+        }
+      }
+    }
+  }
+}";
+
+/// Change history of a repository hosted on GitHub, listing the commits with the GraphQL API.
+///
+/// GraphQL lists a hundred commits per request with their author, date and message. It doesn't tell which files a
+/// commit changed, but it does for the pull request a commit merged, so the commits squashing a pull request, or
+/// rebasing a single commit one, get their files in the same request. The other commits, merge commits, and the commits renaming files,
+/// whose previous paths pull requests don't tell, fall back to one REST request comparing them with their first
+/// parent. So do all the commits when the analysis needs the changed lines, which pull requests don't tell either.
+pub struct GitHubGraphQlHistory {
+    rest: GitHubHistory,
+    // The options of `rest` without the cache, which is only given the commits fetched with REST
+    options: ForgeOptions,
+}
+
+#[derive(Deserialize)]
+struct GraphQlResponse<T> {
+    data: Option<T>,
+    #[serde(default)]
+    errors: Vec<GraphQlError>,
+}
+
+#[derive(Deserialize)]
+struct GraphQlError {
+    message: String,
+}
+
+#[derive(Deserialize)]
+struct HistoryData {
+    repository: Option<Repository>,
+}
+
+#[derive(Deserialize)]
+struct Repository {
+    object: Option<CommitObject>,
+}
+
+#[derive(Deserialize)]
+struct CommitObject {
+    // Missing if the ref points to something else than a commit, like a tree
+    history: Option<CommitHistory>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CommitHistory {
+    page_info: PageInfo,
+    nodes: Vec<HistoryCommit>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PageInfo {
+    has_next_page: bool,
+    end_cursor: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct HistoryCommit {
+    oid: String,
+    message: String,
+    authored_date: Option<DateTime<Utc>>,
+    author: Option<GitActor>,
+    changed_files_if_available: Option<u32>,
+    #[serde(default)]
+    additions: u32,
+    #[serde(default)]
+    deletions: u32,
+    parents: Parents,
+    associated_pull_requests: Option<PullRequests>,
+}
+
+#[derive(Deserialize)]
+struct GitActor {
+    name: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Parents {
+    total_count: u32,
+    nodes: Vec<Parent>,
+}
+
+#[derive(Deserialize)]
+struct Parent {
+    oid: String,
+}
+
+#[derive(Deserialize)]
+struct PullRequests {
+    nodes: Vec<PullRequest>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PullRequest {
+    merge_commit: Option<Parent>,
+    changed_files: u32,
+    additions: u32,
+    deletions: u32,
+    files: Option<PullRequestFiles>,
+}
+
+#[derive(Deserialize)]
+struct PullRequestFiles {
+    nodes: Vec<PullRequestFile>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PullRequestFile {
+    path: String,
+    additions: u32,
+    deletions: u32,
+    change_type: String,
+}
+
+impl HistoryCommit {
+    fn into_changes(self, files: Vec<FileChange>) -> CommitChanges {
+        CommitChanges {
+            sha: self.oid,
+            author: self.author.and_then(|author| author.name),
+            date: self.authored_date,
+            message: self.message,
+            files,
+            num_parents: Some(self.parents.total_count),
+        }
+    }
+
+    /// Files changed by the commit, taken from the pull request it merged if they're certainly the same change: a
+    /// commit with a single parent changing as many files and lines as the whole pull request, all of them listed.
+    fn pull_request_files(&self) -> Option<Vec<FileChange>> {
+        if self.parents.total_count != 1 {
+            return None;
+        }
+        let pull_request =
+            self.associated_pull_requests
+                .as_ref()?
+                .nodes
+                .iter()
+                .find(|pull_request| {
+                    pull_request
+                        .merge_commit
+                        .as_ref()
+                        .is_some_and(|merge_commit| merge_commit.oid == self.oid)
+                })?;
+        let files = &pull_request.files.as_ref()?.nodes;
+        let same_change = Some(pull_request.changed_files) == self.changed_files_if_available
+            && (pull_request.additions, pull_request.deletions) == (self.additions, self.deletions)
+            && files.len() == pull_request.changed_files as usize;
+        if !same_change || files.iter().any(|file| file.change_type == "RENAMED") {
+            return None;
+        }
+        Some(
+            files
+                .iter()
+                .map(|file| FileChange {
+                    filename: file.path.clone(),
+                    additions: file.additions,
+                    deletions: file.deletions,
+                    ..Default::default()
+                })
+                .collect(),
+        )
+    }
+}
+
+#[derive(Deserialize)]
+struct Comparison {
+    #[serde(default)]
+    files: Vec<DiffEntry>,
+}
+
+impl GitHubGraphQlHistory {
+    /// Uses `rest` for everything the GraphQL API can't tell, with its same options.
+    pub fn new(rest: GitHubHistory) -> Self {
+        let options = ForgeOptions {
+            cache: None,
+            ..rest.options.clone()
+        };
+        GitHubGraphQlHistory { rest, options }
+    }
+
+    /// A page of the commits, with the cursor of the next page if any.
+    async fn history_page(
+        &self,
+        query: &HistoryQuery,
+        cursor: Option<String>,
+    ) -> Result<(Vec<HistoryCommit>, Option<String>)> {
+        let body = json!({
+            "query": HISTORY_QUERY,
+            "variables": {
+                "owner": self.rest.owner,
+                "repo": self.rest.repo,
+                "ref": query.git_ref.as_deref().unwrap_or("HEAD"),
+                "since": query.since.to_rfc3339(),
+                "until": query.until.map(|until| until.to_rfc3339()),
+                "first": PAGE_SIZE,
+                "cursor": cursor,
+            },
+        });
+        let response: GraphQlResponse<HistoryData> = self.rest.post("/graphql", &body).await?;
+        if let Some(error) = response.errors.first() {
+            return Err(anyhow!("GitHub GraphQL API failed: {}", error.message));
+        }
+
+        let history = response
+            .data
+            .and_then(|data| data.repository)
+            .ok_or_else(|| {
+                anyhow!(
+                    "GitHub returned no repository {}/{}",
+                    self.rest.owner,
+                    self.rest.repo
+                )
+            })?
+            .object
+            .and_then(|object| object.history)
+            .ok_or_else(|| anyhow!("GitHub returned no commit for the ref {:?}", query.git_ref))?;
+        let next_cursor = match history.page_info.has_next_page {
+            true => history.page_info.end_cursor,
+            false => None,
+        };
+        Ok((history.nodes, next_cursor))
+    }
+
+    /// Changes of the commit, from the cache, from the pull request it merged, or else from the REST API. Files taken
+    /// from pull requests have no changed lines, so they're neither used when the analysis needs them nor cached.
+    async fn get_commit_changes(
+        &self,
+        commit: HistoryCommit,
+        changed_lines: bool,
+    ) -> Result<CommitChanges> {
+        let cache = self.rest.options.cache.as_ref();
+        if let Some(cached_commit) = match cache {
+            Some(cache) => cache.load(&commit.oid).await,
+            None => None,
+        } {
+            return Ok(cached_commit);
+        }
+        if !changed_lines {
+            if let Some(files) = commit.pull_request_files() {
+                return Ok(commit.into_changes(files));
+            }
+        }
+
+        let files = self.get_diff_entries(&commit).await?;
+        let commit_changes = commit.into_changes(files.into_iter().map(FileChange::from).collect());
+        if let Some(cache) = cache {
+            // The commit is fine even if we can't cache it, it will be fetched again next time
+            if let Err(error) = cache.store(&commit_changes).await {
+                log::warn!("Couldn't cache commit {}: {error:#}", commit_changes.sha);
+            }
+        }
+        Ok(commit_changes)
+    }
+
+    /// Files changed by the commit, comparing it with its first parent.
+    async fn get_diff_entries(&self, commit: &HistoryCommit) -> Result<Vec<DiffEntry>> {
+        let repo_route = self.rest.repo_route();
+        let diff_entries = match (
+            commit.changed_files_if_available,
+            commit.parents.nodes.first(),
+        ) {
+            (Some(0), _) => vec![],
+            (_, Some(parent)) => {
+                let comparison: Comparison = self
+                    .rest
+                    .get(&format!(
+                        "{repo_route}/compare/{}...{}",
+                        parent.oid, commit.oid
+                    ))
+                    .await?;
+                comparison.files
+            }
+            // The first commit has nothing to compare with
+            (_, None) => {
+                let repo_commit: RepoCommit = self
+                    .rest
+                    .get(&format!("{repo_route}/commits/{}", commit.oid))
+                    .await?;
+                repo_commit.files.unwrap_or_default()
+            }
+        };
+        Ok(diff_entries)
+    }
+}
+
+#[async_trait::async_trait]
+impl ChangeHistoryProvider for GitHubGraphQlHistory {
+    async fn commits<'a>(&'a self, query: &HistoryQuery) -> Result<CommitStream<'a>> {
+        let first_page = self.history_page(query, None).await?;
+        let page_query = query.clone();
+        let listing = paginate(first_page, move |cursor| {
+            let query = page_query.clone();
+            async move { self.history_page(&query, Some(cursor)).await }
+        });
+
+        let changed_lines = query.changed_lines;
+        Ok(fetch_commits(
+            listing,
+            query,
+            &self.options,
+            |commit| commit.oid.clone(),
+            move |commit| self.get_commit_changes(commit, changed_lines),
+        ))
+    }
+
+    async fn commit_date(&self, git_ref: &str) -> Result<DateTime<Utc>> {
+        self.rest.commit_date(git_ref).await
+    }
+
+    async fn file_contents(&self, path: &str, git_ref: Option<&str>) -> Result<String> {
+        self.rest.file_contents(path, git_ref).await
+    }
+
+    async fn last_commit_before(
+        &self,
+        git_ref: Option<&str>,
+        date: DateTime<Utc>,
+    ) -> Result<Option<String>> {
+        self.rest.last_commit_before(git_ref, date).await
+    }
+
+    async fn issue_labels(&self, number: u64) -> Result<Vec<String>> {
+        self.rest.issue_labels(number).await
+    }
+}
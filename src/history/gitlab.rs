@@ -0,0 +1,227 @@
+use super::{
+    fetch_commits, parse_changed_lines, ChangeHistoryProvider, CommitChanges, CommitStream,
+    FileChange, ForgeOptions, HistoryQuery, PagedApi, WithForgeOptions,
+};
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use futures_util::{StreamExt, TryStreamExt};
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+
+pub const GITLAB_DEFAULT_BASE_URL: &str = "https://gitlab.com";
+
+const PAGE_SIZE: u64 = 100;
+
+/// Change history of a project hosted on GitLab (gitlab.com or a self-hosted instance).
+pub struct GitLabHistory {
+    client: reqwest::Client,
+    base_url: String,
+    project: String,
+    token: String,
+    options: ForgeOptions,
+}
+
+#[derive(Deserialize)]
+struct GitLabCommit {
+    id: String,
+    #[serde(default)]
+    author_name: Option<String>,
+    #[serde(default)]
+    authored_date: Option<DateTime<Utc>>,
+    #[serde(default)]
+    message: String,
+    parent_ids: Option<Vec<String>>,
+}
+
+#[derive(Deserialize)]
+struct GitLabCommitDetails {
+    committed_date: DateTime<Utc>,
+}
+
+#[derive(Deserialize)]
+struct GitLabDiff {
+    old_path: String,
+    new_path: String,
+    #[serde(default)]
+    renamed_file: bool,
+    /// Unified diff of the file, without the `---`/`+++` header
+    #[serde(default)]
+    diff: String,
+}
+
+impl GitLabDiff {
+    /// GitLab doesn't report line counts per file, so we count them from the diff hunks.
+    fn count_lines(&self, prefix: char) -> u32 {
+        self.diff
+            .lines()
+            .filter(|line| line.starts_with(prefix))
+            .count() as u32
+    }
+}
+
+impl GitLabHistory {
+    /// `project` can be either the numeric id of the project or its full path, like `group/project`.
+    pub fn new(base_url: &str, project: &str, token: &str) -> Self {
+        GitLabHistory {
+            client: reqwest::Client::new(),
+            base_url: base_url.trim_end_matches('/').to_string(),
+            project: project.to_string(),
+            token: token.to_string(),
+            options: ForgeOptions::default(),
+        }
+    }
+
+    fn project_url(&self) -> String {
+        format!(
+            "{}/api/v4/projects/{}",
+            self.base_url,
+            self.project.replace('/', "%2F")
+        )
+    }
+
+    async fn get_commit_changes(&self, commit: GitLabCommit) -> Result<CommitChanges> {
+        let sha = commit.id;
+        let diff_url = format!("{}/repository/commits/{}/diff", self.project_url(), sha);
+        let files = self
+            .list::<GitLabDiff>(diff_url, vec![])
+            .await?
+            .map_ok(|diff| FileChange {
+                additions: diff.count_lines('+'),
+                deletions: diff.count_lines('-'),
+                changed_lines: parse_changed_lines(&diff.diff),
+                previous_filename: diff.renamed_file.then_some(diff.old_path),
+                filename: diff.new_path,
+            })
+            .try_collect()
+            .await?;
+        Ok(CommitChanges {
+            sha,
+            author: commit.author_name,
+            date: commit.authored_date,
+            message: commit.message,
+            files,
+            num_parents: commit.parent_ids.map(|parent_ids| parent_ids.len() as u32),
+        })
+    }
+}
+
+#[async_trait::async_trait]
+impl PagedApi for GitLabHistory {
+    const FIRST_PAGE: u64 = 1;
+
+    async fn get_page<T: DeserializeOwned + Send>(
+        &self,
+        url: &str,
+        query: &[(&str, String)],
+        page: u64,
+    ) -> Result<(Vec<T>, Option<u64>)> {
+        let response = self
+            .client
+            .get(url)
+            .header("PRIVATE-TOKEN", &self.token)
+            .query(query)
+            .query(&[("page", page), ("per_page", PAGE_SIZE)])
+            .send()
+            .await?
+            .error_for_status()?;
+
+        // GitLab leaves this header empty on the last page
+        let next_page = response
+            .headers()
+            .get("x-next-page")
+            .and_then(|next_page| next_page.to_str().ok())
+            .and_then(|next_page| next_page.parse().ok());
+
+        Ok((response.json().await?, next_page))
+    }
+}
+
+impl WithForgeOptions for GitLabHistory {
+    fn forge_options(&mut self) -> &mut ForgeOptions {
+        &mut self.options
+    }
+}
+
+#[async_trait::async_trait]
+impl ChangeHistoryProvider for GitLabHistory {
+    async fn commits<'a>(&'a self, query: &HistoryQuery) -> Result<CommitStream<'a>> {
+        let commits_url = format!("{}/repository/commits", self.project_url());
+        let mut params = vec![("since", query.since.to_rfc3339())];
+        if let Some(until) = query.until {
+            params.push(("until", until.to_rfc3339()));
+        }
+        if let Some(git_ref) = &query.git_ref {
+            params.push(("ref_name", git_ref.clone()));
+        }
+
+        let commits_stream = self.list::<GitLabCommit>(commits_url, params).await?;
+
+        // The commits listing doesn't include the changed files, so we need to fetch every commit diff
+        Ok(fetch_commits(
+            commits_stream.boxed(),
+            query,
+            &self.options,
+            |commit| commit.id.clone(),
+            move |commit| self.get_commit_changes(commit),
+        ))
+    }
+
+    async fn commit_date(&self, git_ref: &str) -> Result<DateTime<Utc>> {
+        let commit_url = format!(
+            "{}/repository/commits/{}",
+            self.project_url(),
+            git_ref.replace('/', "%2F")
+        );
+        let commit: GitLabCommitDetails = self
+            .client
+            .get(commit_url)
+            .header("PRIVATE-TOKEN", &self.token)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        Ok(commit.committed_date)
+    }
+
+    async fn last_commit_before(
+        &self,
+        git_ref: Option<&str>,
+        date: DateTime<Utc>,
+    ) -> Result<Option<String>> {
+        let commits_url = format!("{}/repository/commits", self.project_url());
+        let mut params = vec![("until", date.to_rfc3339()), ("per_page", "1".to_string())];
+        if let Some(git_ref) = git_ref {
+            params.push(("ref_name", git_ref.to_string()));
+        }
+        let commits: Vec<GitLabCommit> = self
+            .client
+            .get(commits_url)
+            .header("PRIVATE-TOKEN", &self.token)
+            .query(&params)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        Ok(commits.into_iter().next().map(|commit| commit.id))
+    }
+
+    async fn file_contents(&self, path: &str, git_ref: Option<&str>) -> Result<String> {
+        let file_url = format!(
+            "{}/repository/files/{}/raw",
+            self.project_url(),
+            path.replace('/', "%2F")
+        );
+        Ok(self
+            .client
+            .get(file_url)
+            .header("PRIVATE-TOKEN", &self.token)
+            .query(&[("ref", git_ref.unwrap_or("HEAD"))])
+            .send()
+            .await?
+            .error_for_status()?
+            .text()
+            .await?)
+    }
+}
@@ -0,0 +1,36 @@
+use regex::Regex;
+use std::ops::RangeInclusive;
+use std::sync::LazyLock;
+
+static HUNK_HEADER: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"^@@ -\d+(?:,\d+)? \+(\d+)(?:,(\d+))? @@").expect("Invalid hunk header regex")
+});
+
+/// Lines of the new version of a file changed by a unified diff, one range per hunk. Hunks only removing lines
+/// change the line right before the removal.
+pub fn parse_changed_lines(patch: &str) -> Vec<RangeInclusive<u32>> {
+    patch
+        .lines()
+        .filter_map(|line| HUNK_HEADER.captures(line))
+        .map(|captures| {
+            let start: u32 = captures[1].parse().unwrap_or(0);
+            let num_lines: u32 = captures
+                .get(2)
+                .map_or(Ok(1), |num_lines| num_lines.as_str().parse())
+                .unwrap_or(1);
+            start..=start + num_lines.saturating_sub(1)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_the_changed_lines_from_the_hunk_headers() {
+        let patch = "@@ -1,3 +1,4 @@ fn main() {\n+use std::io;\n \n-\n@@ -10 +11 @@\n-a\n+b\n@@ -20,2 +21,0 @@\n-c\n-d\n";
+
+        assert_eq!(vec![1..=4, 11..=11, 21..=21], parse_changed_lines(patch));
+    }
+}
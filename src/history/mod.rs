@@ -0,0 +1,441 @@
+use anyhow::{anyhow, Context, Result};
+use chrono::{DateTime, Utc};
+use futures::stream::{self, BoxStream};
+use futures::{future, Future};
+use futures_util::{StreamExt, TryStreamExt};
+use progress::ProgressEvent;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::ops::RangeInclusive;
+
+mod bitbucket;
+mod cache;
+mod failures;
+mod filter;
+mod fixture;
+mod git;
+mod gitea;
+mod github;
+mod github_graphql;
+mod gitlab;
+mod hunks;
+mod progress;
+mod query;
+mod rate_limit;
+mod renames;
+mod store;
+
+pub use bitbucket::BitbucketHistory;
+pub use cache::CommitCache;
+pub use failures::{CommitFetchError, FailedCommit, FailedCommits};
+pub use filter::{CommitFilter, PathFilter, IGNORE_FILENAME};
+pub use fixture::{ForgeFixture, RecordedResponses};
+pub use git::LocalGitHistory;
+pub use gitea::GiteaHistory;
+pub use github::{GitHubHistory, GITHUB_DEFAULT_BASE_URL};
+pub use github_graphql::GitHubGraphQlHistory;
+pub use gitlab::{GitLabHistory, GITLAB_DEFAULT_BASE_URL};
+pub use hunks::parse_changed_lines;
+pub use progress::{MiningProgress, Progress};
+pub use query::{HistoryQuery, WindowBound};
+pub use renames::RenameTracker;
+pub use store::HistoryStore;
+
+/// A file touched by a commit.
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct FileChange {
+    pub filename: String,
+    /// Path of the file before the commit, if the commit renamed it
+    pub previous_filename: Option<String>,
+    /// Lines added to the file, 0 if the backend doesn't report it
+    pub additions: u32,
+    /// Lines removed from the file, 0 if the backend doesn't report it
+    pub deletions: u32,
+    /// Lines of the file, as it is after the commit, changed by the commit. Empty if the backend doesn't report it
+    pub changed_lines: Vec<RangeInclusive<u32>>,
+}
+
+impl FileChange {
+    pub fn lines_changed(&self) -> u32 {
+        self.additions + self.deletions
+    }
+}
+
+/// A commit together with the list of files it changed.
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct CommitChanges {
+    pub sha: String,
+    /// Name of the author of the commit, if the backend tells it
+    pub author: Option<String>,
+    /// When the commit was authored, if the backend tells it
+    pub date: Option<DateTime<Utc>>,
+    /// Commit message, or only its first line for local repositories
+    pub message: String,
+    pub files: Vec<FileChange>,
+    /// Number of parents of the commit, more than one for merges, if the backend tells it
+    #[serde(default)]
+    pub num_parents: Option<u32>,
+}
+
+#[cfg(test)]
+impl CommitChanges {
+    /// A commit changing `filenames`, and nothing else known about it. Tests fill in the fields they need with the
+    /// struct update syntax, so new fields don't touch them.
+    pub(crate) fn changing(sha: &str, filenames: &[&str]) -> Self {
+        CommitChanges {
+            sha: sha.into(),
+            files: filenames
+                .iter()
+                .map(|filename| FileChange {
+                    filename: filename.to_string(),
+                    ..Default::default()
+                })
+                .collect(),
+            ..Default::default()
+        }
+    }
+}
+
+/// How many commits the forges fetch at once by default, low enough to stay within the secondary rate limits of
+/// GitHub.
+pub const DEFAULT_CONCURRENCY: usize = 8;
+
+/// How the forges fetch the changes of the listed commits, the same for all of them.
+#[derive(Debug, Clone)]
+pub struct ForgeOptions {
+    /// How many commits to fetch at once
+    pub concurrency: usize,
+    /// Commits fetched in previous runs, if caching
+    pub cache: Option<CommitCache>,
+}
+
+impl Default for ForgeOptions {
+    fn default() -> Self {
+        ForgeOptions {
+            concurrency: DEFAULT_CONCURRENCY,
+            cache: None,
+        }
+    }
+}
+
+/// Builder methods for the [`ForgeOptions`] of a forge provider.
+pub trait WithForgeOptions: Sized {
+    fn forge_options(&mut self) -> &mut ForgeOptions;
+
+    /// Fetches up to `concurrency` commits at once, instead of `DEFAULT_CONCURRENCY`.
+    fn with_concurrency(mut self, concurrency: usize) -> Self {
+        self.forge_options().concurrency = concurrency;
+        self
+    }
+
+    /// Keeps the fetched commits in `cache`, if any, and takes them from it in the next runs.
+    fn with_cache(mut self, cache: Option<CommitCache>) -> Self {
+        self.forge_options().cache = cache;
+        self
+    }
+}
+
+/// Stream of the commits of a repository, from the most recent to the oldest. The order matters to follow renames.
+pub type CommitStream<'a> = BoxStream<'a, Result<CommitChanges>>;
+
+/// Source of the change history of a repository.
+///
+/// Every backend (GitHub, GitLab, Bitbucket, Gitea, a local git checkout or recorded fixtures) only has to know how
+/// to stream its commits, the aggregation on top of them is shared.
+#[async_trait::async_trait]
+pub trait ChangeHistoryProvider: Sync {
+    async fn commits<'a>(&'a self, query: &HistoryQuery) -> Result<CommitStream<'a>>;
+
+    /// Date of the commit a git ref (branch, tag or SHA) points to.
+    async fn commit_date(&self, git_ref: &str) -> Result<DateTime<Utc>>;
+
+    /// Contents of a file at a git ref, or at the default branch if none.
+    async fn file_contents(&self, path: &str, git_ref: Option<&str>) -> Result<String>;
+
+    /// SHA of the most recent commit of a git ref, or of the default branch if none, made before `date`, if any.
+    /// Providers that can look it up directly should, this takes the first commit of a listing.
+    async fn last_commit_before(
+        &self,
+        git_ref: Option<&str>,
+        date: DateTime<Utc>,
+    ) -> Result<Option<String>> {
+        let query = HistoryQuery {
+            git_ref: git_ref.map(String::from),
+            ..HistoryQuery::new(DateTime::<Utc>::MIN_UTC, Some(date))
+        };
+        let mut commits = self.commits(&query).await?;
+        Ok(match commits.next().await {
+            Some(commit) => Some(commit?.sha),
+            None => None,
+        })
+    }
+
+    /// Labels of an issue or pull request of the repository, for the forges linking them to commits.
+    async fn issue_labels(&self, number: u64) -> Result<Vec<String>> {
+        Err(anyhow!(
+            "Looking up the labels of issue #{number} isn't supported for this repository"
+        ))
+    }
+}
+
+/// Recorded history, mostly useful to feed the aggregation with fixtures. Commits are returned as recorded,
+/// regardless of the query.
+#[async_trait::async_trait]
+impl ChangeHistoryProvider for Vec<CommitChanges> {
+    async fn commits<'a>(&'a self, _query: &HistoryQuery) -> Result<CommitStream<'a>> {
+        Ok(stream::iter(self.iter().cloned().map(Ok)).boxed())
+    }
+
+    async fn commit_date(&self, git_ref: &str) -> Result<DateTime<Utc>> {
+        Err(anyhow!(
+            "Recorded histories can't resolve git refs like {git_ref}"
+        ))
+    }
+
+    async fn file_contents(&self, path: &str, _git_ref: Option<&str>) -> Result<String> {
+        Err(anyhow!(
+            "Recorded histories don't keep the contents of {path}"
+        ))
+    }
+}
+
+/// Fetches the changes of every listed commit, up to the `concurrency` of the `options` at once, keeping the order
+/// of the listing. Commits in their `cache` aren't fetched again, and the fetched ones are added to it. Errors tell
+/// the SHA of the commit, from `listed_sha`. The progress is reported to the one of the `query`.
+pub(crate) fn fetch_commits<'a, T, S, F, Fut>(
+    listing: BoxStream<'a, Result<T>>,
+    query: &HistoryQuery,
+    options: &'a ForgeOptions,
+    listed_sha: S,
+    fetch_commit: F,
+) -> CommitStream<'a>
+where
+    T: Send + 'a,
+    S: Fn(&T) -> String + Send + 'a,
+    F: Fn(T) -> Fut + Send + 'a,
+    Fut: Future<Output = Result<CommitChanges>> + Send + 'a,
+{
+    let (progress, since) = (query.progress.clone(), query.since);
+    let cache = options.cache.as_ref();
+    let until = query.until.unwrap_or_else(Utc::now);
+    listing
+        .map(move |listed_commit| {
+            let commit_changes = listed_commit.map(|listed_commit| {
+                let sha = listed_sha(&listed_commit);
+                (sha, fetch_commit(listed_commit))
+            });
+            let progress = progress.clone();
+            let record = move |event| progress.record(event, since, until);
+            async move {
+                let (sha, commit_changes) =
+                    commit_changes.inspect_err(|_| record(ProgressEvent::Failed))?;
+                record(ProgressEvent::Listed);
+                if let Some(cached_commit) = match cache {
+                    Some(cache) => cache.load(&sha).await,
+                    None => None,
+                } {
+                    record(ProgressEvent::CacheHit(cached_commit.date));
+                    return Ok(cached_commit);
+                }
+                let commit_changes = commit_changes
+                    .await
+                    .inspect_err(|_| record(ProgressEvent::Failed))
+                    .context(CommitFetchError { sha })?;
+                record(ProgressEvent::Fetched(commit_changes.date));
+                if let Some(cache) = cache {
+                    // The commit is fine even if we can't cache it, it will be fetched again next time
+                    if let Err(error) = cache.store(&commit_changes).await {
+                        log::warn!("Couldn't cache commit {}: {error:#}", commit_changes.sha);
+                    }
+                }
+                Ok(commit_changes)
+            }
+        })
+        .buffered(options.concurrency.max(1))
+        .chain(finished(query))
+        .boxed()
+}
+
+/// Reports the progress of reading the `commits` of a local history, like a clone or the store, to the one of the
+/// `query`. Nothing has to be fetched, so they're fetched as soon as they're listed.
+pub(crate) fn report_progress<'a>(
+    commits: CommitStream<'a>,
+    query: &HistoryQuery,
+) -> CommitStream<'a> {
+    let (progress, since) = (query.progress.clone(), query.since);
+    let until = query.until.unwrap_or_else(Utc::now);
+    commits
+        .inspect(move |commit| match commit {
+            Ok(commit) => {
+                progress.record(ProgressEvent::Listed, since, until);
+                progress.record(ProgressEvent::Fetched(commit.date), since, until);
+            }
+            Err(_) => progress.record(ProgressEvent::Failed, since, until),
+        })
+        .chain(finished(query))
+        .boxed()
+}
+
+/// An empty stream reporting the end of the listing of the `query` when it's reached.
+fn finished<'a>(query: &HistoryQuery) -> CommitStream<'a> {
+    let (progress, since) = (query.progress.clone(), query.since);
+    let until = query.until.unwrap_or_else(Utc::now);
+    stream::once(async move {
+        progress.record(ProgressEvent::Finished, since, until);
+        None
+    })
+    .filter_map(future::ready)
+    .boxed()
+}
+
+/// Turns a paginated listing into a stream of items, starting with its already fetched `first_page`. `fetch_page`
+/// gets the cursor of a page (a page number, an offset or an opaque GraphQL cursor) and returns its items together
+/// with the cursor of the next page, if any.
+pub(crate) fn paginate<'a, C, T, F, Fut>(
+    first_page: (Vec<T>, Option<C>),
+    fetch_page: F,
+) -> BoxStream<'a, Result<T>>
+where
+    C: Send + 'a,
+    T: Send + 'a,
+    F: Fn(C) -> Fut + Send + 'a,
+    Fut: Future<Output = Result<(Vec<T>, Option<C>)>> + Send + 'a,
+{
+    let (first_items, next_page) = first_page;
+    let next_pages = stream::try_unfold((next_page, fetch_page), |(page, fetch_page)| async move {
+        match page {
+            Some(page) => {
+                let (items, next_page) = fetch_page(page).await?;
+                let items = items.into_iter().map(Ok::<T, anyhow::Error>);
+                Ok::<_, anyhow::Error>(Some((stream::iter(items), (next_page, fetch_page))))
+            }
+            None => Ok(None),
+        }
+    })
+    .try_flatten();
+    stream::iter(first_items.into_iter().map(Ok))
+        .chain(next_pages)
+        .boxed()
+}
+
+/// A forge REST API whose listings come in pages. Each forge only tells how to fetch a page and find the next one.
+#[async_trait::async_trait]
+pub(crate) trait PagedApi: Sync {
+    /// Cursor of the first page: a page number or an offset, depending on the forge
+    const FIRST_PAGE: u64;
+
+    /// Fetches the page of the listing at `url` starting at `page`, and returns its items together with the cursor
+    /// of the next page, if any.
+    async fn get_page<T: DeserializeOwned + Send>(
+        &self,
+        url: &str,
+        query: &[(&str, String)],
+        page: u64,
+    ) -> Result<(Vec<T>, Option<u64>)>;
+
+    /// Streams every item of the listing at `url`. The first page is fetched right away, so a wrong repository or
+    /// token fails early instead of in the middle of the stream.
+    async fn list<'a, T: DeserializeOwned + Send + 'a>(
+        &'a self,
+        url: String,
+        query: Vec<(&'a str, String)>,
+    ) -> Result<BoxStream<'a, Result<T>>> {
+        let first_page = self.get_page::<T>(&url, &query, Self::FIRST_PAGE).await?;
+        Ok(paginate(first_page, move |page| {
+            let (url, query) = (url.clone(), query.clone());
+            async move { self.get_page(&url, &query, page).await }
+        }))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn fetch_commits_concurrently_in_the_listing_order() {
+        let in_flight = AtomicUsize::new(0);
+        let max_in_flight = AtomicUsize::new(0);
+        let listing = stream::iter((0..10).map(Ok)).boxed();
+
+        let query = HistoryQuery::default();
+        let options = ForgeOptions {
+            concurrency: 3,
+            cache: None,
+        };
+        let commits: Vec<_> =
+            fetch_commits(listing, &query, &options, u64::to_string, |number: u64| {
+                let (in_flight, max_in_flight) = (&in_flight, &max_in_flight);
+                async move {
+                    let fetching = in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+                    max_in_flight.fetch_max(fetching, Ordering::SeqCst);
+                    // The first commits take the longest to fetch
+                    tokio::time::sleep(Duration::from_millis(20 - 2 * number)).await;
+                    in_flight.fetch_sub(1, Ordering::SeqCst);
+                    Ok(CommitChanges {
+                        sha: number.to_string(),
+                        ..Default::default()
+                    })
+                }
+            })
+            .map(|commit| commit.unwrap().sha)
+            .collect()
+            .await;
+
+        let expected: Vec<_> = (0..10).map(|number| number.to_string()).collect();
+        assert_eq!(expected, commits);
+        assert_eq!(3, max_in_flight.load(Ordering::SeqCst));
+    }
+
+    #[tokio::test]
+    async fn report_the_progress_of_fetching_commits() {
+        let cache_directory = tempfile::tempdir().unwrap();
+        let cache = CommitCache::new(cache_directory.path().to_path_buf());
+        cache
+            .store(&CommitChanges {
+                sha: "1".into(),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        let listing = stream::iter([Ok(0), Ok(1), Err(anyhow!("Bad page")), Ok(2)]).boxed();
+        let query = HistoryQuery::default();
+        let options = ForgeOptions {
+            concurrency: 2,
+            cache: Some(cache),
+        };
+
+        let commits: Vec<_> = fetch_commits(
+            listing,
+            &query,
+            &options,
+            u64::to_string,
+            |number: u64| async move {
+                match number {
+                    2 => Err(anyhow!("502 Bad Gateway")),
+                    _ => Ok(CommitChanges {
+                        sha: number.to_string(),
+                        ..Default::default()
+                    }),
+                }
+            },
+        )
+        .collect()
+        .await;
+
+        let progress = query.progress.progress();
+        assert_eq!(4, commits.len());
+        assert_eq!(
+            (3, 1, 1, 2, true),
+            (
+                progress.listed,
+                progress.fetched,
+                progress.cache_hits,
+                progress.errors,
+                progress.finished
+            )
+        );
+    }
+}
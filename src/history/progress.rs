@@ -0,0 +1,184 @@
+use chrono::{DateTime, Utc};
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// How far mining the history got.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Progress {
+    /// Commits listed by the forge, fetched or not yet
+    pub listed: usize,
+    /// Commits whose changes were fetched from the forge
+    pub fetched: usize,
+    /// Commits whose changes came from the cache instead
+    pub cache_hits: usize,
+    /// Commits, or pages of the listing, that couldn't be fetched
+    pub errors: usize,
+    /// Share of the analysis window covered by the commits done so far, from the most recent, between 0 and 1
+    pub window_covered: f64,
+    /// Time left to cover the whole window at the pace so far, once part of it is covered
+    pub eta: Option<Duration>,
+    /// Whether the last listing was mined to its end
+    pub finished: bool,
+}
+
+/// What happened to a commit while mining the history.
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum ProgressEvent {
+    Listed,
+    CacheHit(Option<DateTime<Utc>>),
+    Fetched(Option<DateTime<Utc>>),
+    Failed,
+    Finished,
+}
+
+type ProgressCallback = dyn Fn(&Progress) + Send + Sync;
+
+#[derive(Default)]
+struct ProgressState {
+    progress: Progress,
+    started: Option<Instant>,
+}
+
+/// Reports the progress of mining the history to a callback, on every listed, fetched or failed commit. Clones
+/// share the same progress, and every mining pass of a run, like the one of the function churn after the one of the
+/// heat map, starts over once the previous one finished.
+#[derive(Clone, Default)]
+pub struct MiningProgress {
+    state: Arc<Mutex<ProgressState>>,
+    callback: Option<Arc<ProgressCallback>>,
+}
+
+impl MiningProgress {
+    pub fn new(callback: impl Fn(&Progress) + Send + Sync + 'static) -> Self {
+        MiningProgress {
+            state: Arc::default(),
+            callback: Some(Arc::new(callback)),
+        }
+    }
+
+    pub fn progress(&self) -> Progress {
+        self.state.lock().unwrap().progress
+    }
+
+    /// Records what happened to a commit of the analysis window, from `since` to `until`.
+    pub(crate) fn record(&self, event: ProgressEvent, since: DateTime<Utc>, until: DateTime<Utc>) {
+        let progress = {
+            let mut state = self.state.lock().unwrap();
+            if state.progress.finished && !matches!(event, ProgressEvent::Finished) {
+                *state = ProgressState::default();
+            }
+            let started = *state.started.get_or_insert_with(Instant::now);
+            let progress = &mut state.progress;
+            progress.finished = matches!(event, ProgressEvent::Finished);
+            let date = match event {
+                ProgressEvent::Listed => {
+                    progress.listed += 1;
+                    None
+                }
+                ProgressEvent::CacheHit(date) => {
+                    progress.cache_hits += 1;
+                    date
+                }
+                ProgressEvent::Fetched(date) => {
+                    progress.fetched += 1;
+                    date
+                }
+                ProgressEvent::Failed => {
+                    progress.errors += 1;
+                    None
+                }
+                ProgressEvent::Finished => None,
+            };
+            // Commits come from the most recent, so the oldest one done tells how much of the window is left
+            if let Some(date) = date {
+                let window = (until - since).num_seconds().max(1) as f64;
+                let covered = ((until - date).num_seconds() as f64 / window).clamp(0.0, 1.0);
+                progress.window_covered = progress.window_covered.max(covered);
+            }
+            if progress.window_covered > 0.0 {
+                let elapsed = started.elapsed().as_secs_f64();
+                progress.eta = Some(Duration::from_secs_f64(
+                    elapsed * (1.0 - progress.window_covered) / progress.window_covered,
+                ));
+            }
+            *progress
+        };
+        if let Some(callback) = &self.callback {
+            callback(&progress);
+        }
+    }
+}
+
+impl fmt::Debug for MiningProgress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("MiningProgress")
+            .field(&self.progress())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn report_the_counts_and_the_covered_window_to_the_callback() {
+        let reported = Arc::new(Mutex::new(Vec::new()));
+        let progress = MiningProgress::new({
+            let reported = reported.clone();
+            move |progress| reported.lock().unwrap().push(*progress)
+        });
+        let day = |day| Some(Utc.with_ymd_and_hms(2023, 1, day, 0, 0, 0).unwrap());
+        let (since, until) = (day(1).unwrap(), day(11).unwrap());
+
+        progress.record(ProgressEvent::Listed, since, until);
+        progress.clone().record(ProgressEvent::Listed, since, until);
+        progress.record(ProgressEvent::CacheHit(day(9)), since, until);
+        progress.record(ProgressEvent::Fetched(day(6)), since, until);
+        progress.record(ProgressEvent::Failed, since, until);
+        progress.record(ProgressEvent::Finished, since, until);
+
+        let reported = reported.lock().unwrap();
+        assert_eq!(6, reported.len());
+        assert_eq!(None, reported[1].eta);
+        assert_eq!(0.2, reported[2].window_covered);
+        assert!(!reported[4].finished);
+        let last = reported[5];
+        assert_eq!(
+            (2, 1, 1, 1, 0.5),
+            (
+                last.listed,
+                last.fetched,
+                last.cache_hits,
+                last.errors,
+                last.window_covered
+            )
+        );
+        assert!(last.eta.is_some() && last.finished);
+        assert_eq!(last, progress.progress());
+    }
+
+    #[test]
+    fn start_over_on_the_next_mining_pass() {
+        let progress = MiningProgress::default();
+        let day = |day| Utc.with_ymd_and_hms(2023, 1, day, 0, 0, 0).unwrap();
+        let (since, until) = (day(1), day(11));
+        progress.record(ProgressEvent::Fetched(Some(day(2))), since, until);
+        progress.record(ProgressEvent::Finished, since, until);
+
+        progress.record(ProgressEvent::Listed, since, until);
+
+        let next_pass = progress.progress();
+        assert_eq!(
+            (1, 0, 0.0, false),
+            (
+                next_pass.listed,
+                next_pass.fetched,
+                next_pass.window_covered,
+                next_pass.finished
+            )
+        );
+    }
+}
@@ -0,0 +1,172 @@
+use super::{ChangeHistoryProvider, CommitFilter, FailedCommits, MiningProgress, PathFilter};
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Days, Duration, Months, NaiveDate, Utc};
+use regex::Regex;
+use std::ops::Sub;
+use std::str::FromStr;
+use std::sync::LazyLock;
+
+const DEFAULT_WINDOW_DAYS: i64 = 365;
+
+static RELATIVE_DURATION: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^(\d+)([dwmy])$").expect("Invalid duration regex"));
+
+/// Which part of the history of a repository we want to analyze.
+#[derive(Debug, Clone)]
+pub struct HistoryQuery {
+    pub since: DateTime<Utc>,
+    pub until: Option<DateTime<Utc>>,
+    /// Branch, tag or SHA whose history we mine. The default branch if none
+    pub git_ref: Option<String>,
+    /// Files whose changes are counted
+    pub path_filter: PathFilter,
+    /// Commits whose changes are counted
+    pub commit_filter: CommitFilter,
+    /// Where the analyses record the commits they had to leave out
+    pub failed_commits: FailedCommits,
+    /// Where the forges report how far mining the history got
+    pub progress: MiningProgress,
+    /// Whether the analysis needs the lines changed in every file. Providers that have to ask for them separately,
+    /// like a local clone, leave them out otherwise
+    pub changed_lines: bool,
+}
+
+impl Default for HistoryQuery {
+    /// The last year of history
+    fn default() -> Self {
+        HistoryQuery {
+            since: HistoryQuery::default_since(Utc::now()),
+            until: None,
+            git_ref: None,
+            path_filter: PathFilter::default(),
+            commit_filter: CommitFilter::default(),
+            failed_commits: FailedCommits::default(),
+            progress: MiningProgress::default(),
+            changed_lines: false,
+        }
+    }
+}
+
+impl HistoryQuery {
+    pub fn new(since: DateTime<Utc>, until: Option<DateTime<Utc>>) -> Self {
+        HistoryQuery {
+            since,
+            until,
+            git_ref: None,
+            path_filter: PathFilter::default(),
+            commit_filter: CommitFilter::default(),
+            failed_commits: FailedCommits::default(),
+            progress: MiningProgress::default(),
+            changed_lines: false,
+        }
+    }
+
+    /// Start of the default window, the last year before `now`.
+    pub fn default_since(now: DateTime<Utc>) -> DateTime<Utc> {
+        now.sub(Duration::days(DEFAULT_WINDOW_DAYS))
+    }
+
+    /// Whether a commit made at `date` falls in the analysis window.
+    pub fn contains(&self, date: DateTime<Utc>) -> bool {
+        date >= self.since && self.until.iter().all(|until| date <= *until)
+    }
+}
+
+/// One of the ends of the analysis window, as the user can write it: an absolute date (`2023-01-31` or RFC 3339),
+/// a duration relative to now (`90d`, `12w`, `6m`, `1y`) or a git ref/tag (`v1.2.0`).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WindowBound {
+    Date(DateTime<Utc>),
+    DaysAgo(i64),
+    MonthsAgo(u32),
+    Ref(String),
+}
+
+impl FromStr for WindowBound {
+    type Err = anyhow::Error;
+
+    fn from_str(bound: &str) -> Result<Self> {
+        let bound = bound.trim();
+        if bound.is_empty() {
+            return Err(anyhow!("Empty date, duration or ref"));
+        }
+        if let Ok(date) = DateTime::parse_from_rfc3339(bound) {
+            return Ok(WindowBound::Date(date.with_timezone(&Utc)));
+        }
+        if let Ok(date) = NaiveDate::parse_from_str(bound, "%Y-%m-%d") {
+            let midnight = date.and_hms_opt(0, 0, 0).expect("Midnight is always valid");
+            return Ok(WindowBound::Date(DateTime::from_utc(midnight, Utc)));
+        }
+
+        if let Some(captures) = RELATIVE_DURATION.captures(bound) {
+            let amount: u32 = captures[1].parse()?;
+            return Ok(match &captures[2] {
+                "d" => WindowBound::DaysAgo(amount.into()),
+                "w" => WindowBound::DaysAgo(i64::from(amount) * 7),
+                "m" => WindowBound::MonthsAgo(amount),
+                _ => WindowBound::MonthsAgo(
+                    amount
+                        .checked_mul(12)
+                        .ok_or_else(|| anyhow!("{bound} is out of range"))?,
+                ),
+            });
+        }
+
+        Ok(WindowBound::Ref(bound.to_string()))
+    }
+}
+
+impl WindowBound {
+    /// Turns the bound into a date, relative to `now`, asking the provider for the date of the commit when it's a git
+    /// ref.
+    pub async fn resolve<P>(&self, provider: &P, now: DateTime<Utc>) -> Result<DateTime<Utc>>
+    where
+        P: ChangeHistoryProvider + ?Sized,
+    {
+        match self {
+            WindowBound::Date(date) => Ok(*date),
+            WindowBound::DaysAgo(days) => u64::try_from(*days)
+                .ok()
+                .and_then(|days| now.checked_sub_days(Days::new(days)))
+                .ok_or_else(|| anyhow!("{days} days ago is out of range")),
+            WindowBound::MonthsAgo(months) => now
+                .checked_sub_months(Months::new(*months))
+                .ok_or_else(|| anyhow!("{months} months ago is out of range")),
+            WindowBound::Ref(git_ref) => provider.commit_date(git_ref).await,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::history::CommitChanges;
+    use chrono::TimeZone;
+
+    #[test]
+    fn parse_absolute_dates_relative_durations_and_refs() {
+        assert_eq!(
+            WindowBound::Date(Utc.with_ymd_and_hms(2023, 1, 31, 0, 0, 0).unwrap()),
+            "2023-01-31".parse().unwrap()
+        );
+        assert_eq!(
+            WindowBound::Date(Utc.with_ymd_and_hms(2023, 1, 31, 10, 0, 0).unwrap()),
+            "2023-01-31T12:00:00+02:00".parse().unwrap()
+        );
+        assert_eq!(WindowBound::DaysAgo(90), "90d".parse().unwrap());
+        assert_eq!(WindowBound::DaysAgo(14), "2w".parse().unwrap());
+        assert_eq!(WindowBound::MonthsAgo(6), "6m".parse().unwrap());
+        assert_eq!(WindowBound::MonthsAgo(24), "2y".parse().unwrap());
+        assert_eq!(WindowBound::Ref("v1.2.0".into()), "v1.2.0".parse().unwrap());
+        assert!("".parse::<WindowBound>().is_err());
+        assert!("4000000000y".parse::<WindowBound>().is_err());
+    }
+
+    #[tokio::test]
+    async fn refuse_durations_reaching_out_of_the_calendar() {
+        let history: Vec<CommitChanges> = vec![];
+        let bound: WindowBound = "4000000000w".parse().unwrap();
+
+        assert!(bound.resolve(&history, Utc::now()).await.is_err());
+    }
+}
@@ -0,0 +1,116 @@
+use chrono::{DateTime, TimeZone, Utc};
+use std::time::Duration;
+
+/// Retries of a request before giving up on it
+const MAX_RETRIES: u32 = 5;
+// Doubled on every retry of transient errors, up to `MAX_BACKOFF`
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+/// How long to wait before retrying a request that got a `status` response, `None` if it shouldn't be retried.
+/// `header` looks up the headers of the response, and `attempt` is the number of retries so far.
+///
+/// Rate limited requests wait as long as the forge says, in the `Retry-After` header or until the
+/// `X-RateLimit-Reset` time when there are no requests left. Server errors, and rate limits without a hint, are
+/// retried with an exponential backoff.
+pub(crate) fn retry_delay<'a>(
+    status: u16,
+    header: impl Fn(&str) -> Option<&'a str>,
+    attempt: u32,
+    now: DateTime<Utc>,
+) -> Option<Duration> {
+    if attempt >= MAX_RETRIES {
+        return None;
+    }
+    let backoff = INITIAL_BACKOFF
+        .saturating_mul(2u32.saturating_pow(attempt))
+        .min(MAX_BACKOFF);
+
+    match status {
+        403 | 429 => {
+            if let Some(retry_after) = header("retry-after").and_then(|value| value.parse().ok()) {
+                return Some(Duration::from_secs(retry_after));
+            }
+            if header("x-ratelimit-remaining") == Some("0") {
+                return Some(match header("x-ratelimit-reset").and_then(parse_reset) {
+                    // Some slack, so the limit is surely reset when we retry
+                    Some(reset) => (reset - now)
+                        .to_std()
+                        .unwrap_or_default()
+                        .saturating_add(Duration::from_secs(1)),
+                    None => backoff,
+                });
+            }
+            // A 403 without any rate limit hint is a plain lack of permissions
+            (status == 429).then_some(backoff)
+        }
+        500..=599 => Some(backoff),
+        _ => None,
+    }
+}
+
+/// The rate limit reset time, in seconds since the epoch.
+fn parse_reset(value: &str) -> Option<DateTime<Utc>> {
+    Utc.timestamp_opt(value.parse().ok()?, 0).single()
+}
+
+/// Logs the requests left before hitting the rate limit, if the forge tells.
+pub(crate) fn log_remaining_quota<'a>(forge: &str, header: impl Fn(&str) -> Option<&'a str>) {
+    if let (Some(remaining), Some(limit)) =
+        (header("x-ratelimit-remaining"), header("x-ratelimit-limit"))
+    {
+        let reset = header("x-ratelimit-reset")
+            .and_then(parse_reset)
+            .map_or("unknown".to_string(), |reset| reset.to_rfc3339());
+        log::debug!("{forge} rate limit: {remaining} of {limit} requests left, reset at {reset}");
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn headers<'a>(headers: &'a [(&str, &'a str)]) -> impl Fn(&str) -> Option<&'a str> {
+        let headers: HashMap<_, _> = headers
+            .iter()
+            .map(|(name, value)| (name.to_string(), *value))
+            .collect();
+        move |name| headers.get(name).copied()
+    }
+
+    #[test]
+    fn wait_until_the_rate_limit_resets() {
+        let now = Utc.timestamp_opt(1_700_000_000, 0).unwrap();
+        let exhausted = [
+            ("x-ratelimit-remaining", "0"),
+            ("x-ratelimit-reset", "1700000030"),
+        ];
+
+        assert_eq!(
+            Some(Duration::from_secs(31)),
+            retry_delay(403, headers(&exhausted), 0, now)
+        );
+        assert_eq!(
+            Some(Duration::from_secs(120)),
+            retry_delay(429, headers(&[("retry-after", "120")]), 0, now)
+        );
+        assert_eq!(None, retry_delay(403, headers(&[]), 0, now));
+    }
+
+    #[test]
+    fn back_off_exponentially_on_server_errors() {
+        let now = Utc::now();
+
+        assert_eq!(
+            Some(Duration::from_secs(1)),
+            retry_delay(502, headers(&[]), 0, now)
+        );
+        assert_eq!(
+            Some(Duration::from_secs(8)),
+            retry_delay(503, headers(&[]), 3, now)
+        );
+        assert_eq!(None, retry_delay(503, headers(&[]), MAX_RETRIES, now));
+        assert_eq!(None, retry_delay(404, headers(&[]), 0, now));
+    }
+}
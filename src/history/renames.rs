@@ -0,0 +1,96 @@
+use super::CommitChanges;
+use std::collections::HashMap;
+
+/// Follows files across renames, so their whole history is kept under a single path. Commits have to be fed
+/// from the most recent to the oldest.
+#[derive(Debug, Default)]
+pub struct RenameTracker {
+    // Old path -> path of the file at the most recent commit
+    current_paths: HashMap<String, String>,
+    // Path of the file at the most recent commit -> path before the commits seen so far
+    previous_paths: HashMap<String, String>,
+}
+
+impl RenameTracker {
+    /// Moves every file changed by the commit to the path it has at the most recent commit seen so far.
+    pub fn follow(&mut self, mut commit: CommitChanges) -> CommitChanges {
+        for file_change in commit.files.iter_mut() {
+            if let Some(current_path) = self.current_paths.get(&file_change.filename) {
+                file_change.filename = current_path.clone();
+            }
+            // Older commits know the file by its previous path
+            if let Some(previous_filename) = &file_change.previous_filename {
+                self.current_paths
+                    .insert(previous_filename.clone(), file_change.filename.clone());
+                self.previous_paths
+                    .insert(file_change.filename.clone(), previous_filename.clone());
+            }
+        }
+        commit
+    }
+
+    /// Path the file at `current_path` had before the commits seen so far, to read it at an older revision.
+    pub fn path_before<'a>(&'a self, current_path: &'a str) -> &'a str {
+        self.previous_paths
+            .get(current_path)
+            .map_or(current_path, String::as_str)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::history::FileChange;
+
+    fn commit(files: &[(&str, Option<&str>)]) -> CommitChanges {
+        CommitChanges {
+            sha: "sha".into(),
+            files: files
+                .iter()
+                .map(|(filename, previous_filename)| FileChange {
+                    filename: filename.to_string(),
+                    previous_filename: previous_filename.map(String::from),
+                    ..Default::default()
+                })
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    fn filenames(commit: &CommitChanges) -> Vec<&str> {
+        commit
+            .files
+            .iter()
+            .map(|file| file.filename.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn follow_a_file_renamed_twice() {
+        let mut rename_tracker = RenameTracker::default();
+
+        let newest = rename_tracker.follow(commit(&[("src/radar.rs", Some("src/lib.rs"))]));
+        let renamed = rename_tracker.follow(commit(&[("src/lib.rs", Some("lib.rs"))]));
+        let oldest = rename_tracker.follow(commit(&[("lib.rs", None), ("main.rs", None)]));
+
+        assert_eq!(vec!["src/radar.rs"], filenames(&newest));
+        assert_eq!(vec!["src/radar.rs"], filenames(&renamed));
+        assert_eq!(vec!["src/radar.rs", "main.rs"], filenames(&oldest));
+        assert_eq!("lib.rs", rename_tracker.path_before("src/radar.rs"));
+        assert_eq!("main.rs", rename_tracker.path_before("main.rs"));
+    }
+
+    #[test]
+    fn keep_a_new_file_at_the_old_path_of_a_renamed_file_apart() {
+        let mut rename_tracker = RenameTracker::default();
+
+        // A new file took the old path after the file was renamed
+        let new_file = rename_tracker.follow(commit(&[("src/lib.rs", None)]));
+        let renamed = rename_tracker.follow(commit(&[("src/radar.rs", Some("src/lib.rs"))]));
+        let oldest = rename_tracker.follow(commit(&[("src/lib.rs", None)]));
+
+        assert_eq!(vec!["src/lib.rs"], filenames(&new_file));
+        assert_eq!(vec!["src/radar.rs"], filenames(&renamed));
+        assert_eq!(vec!["src/radar.rs"], filenames(&oldest));
+    }
+}
@@ -0,0 +1,670 @@
+use super::{
+    report_progress, ChangeHistoryProvider, CommitChanges, CommitStream, FileChange, HistoryQuery,
+    IGNORE_FILENAME,
+};
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, SecondsFormat, Utc};
+use futures::stream;
+use futures_util::StreamExt;
+use rusqlite::{params, Connection, OptionalExtension};
+use std::path::Path;
+use std::sync::Mutex;
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS commits (
+    sha TEXT PRIMARY KEY,
+    author TEXT,
+    -- RFC 3339 in UTC, so they sort as text
+    date TEXT,
+    message TEXT NOT NULL,
+    num_parents INTEGER,
+    -- The commits are ordered like the forge listed them, from the newest: by the end of the ingested window they
+    -- were listed in, then by their position in that listing
+    listed_until TEXT NOT NULL,
+    listing_position INTEGER NOT NULL
+);
+CREATE INDEX IF NOT EXISTS commits_by_date ON commits (date);
+CREATE INDEX IF NOT EXISTS commits_by_listing ON commits (listed_until DESC, listing_position);
+
+CREATE TABLE IF NOT EXISTS file_changes (
+    sha TEXT NOT NULL REFERENCES commits (sha) ON DELETE CASCADE,
+    filename TEXT NOT NULL,
+    previous_filename TEXT,
+    additions INTEGER NOT NULL,
+    deletions INTEGER NOT NULL,
+    -- JSON array of the changed line ranges
+    changed_lines TEXT NOT NULL,
+    PRIMARY KEY (sha, filename)
+);
+CREATE INDEX IF NOT EXISTS file_changes_by_filename ON file_changes (filename);
+
+CREATE VIEW IF NOT EXISTS renames AS
+    SELECT sha, previous_filename, filename FROM file_changes WHERE previous_filename IS NOT NULL;
+
+CREATE VIEW IF NOT EXISTS authors AS
+    SELECT author, COUNT(*) AS num_commits, MIN(date) AS first_commit, MAX(date) AS last_commit
+    FROM commits WHERE author IS NOT NULL GROUP BY author;
+
+-- Measures of the files at every revision, like their complexity
+CREATE TABLE IF NOT EXISTS metrics (
+    sha TEXT NOT NULL,
+    filename TEXT NOT NULL,
+    metric TEXT NOT NULL,
+    value REAL NOT NULL,
+    PRIMARY KEY (sha, filename, metric)
+);
+
+-- Windows of the history fully ingested
+CREATE TABLE IF NOT EXISTS ingestions (
+    since TEXT NOT NULL,
+    until TEXT NOT NULL,
+    -- NULL for the default branch
+    git_ref TEXT
+);
+
+-- Contents of the files needed without the forge, as of the last ingestion, like the ignore file
+CREATE TABLE IF NOT EXISTS files (
+    filename TEXT PRIMARY KEY,
+    contents TEXT NOT NULL
+);
+";
+
+/// Files stored at every ingestion, to read them without the forge.
+const STORED_FILES: [&str; 1] = [IGNORE_FILENAME];
+
+/// Mined history kept in an SQLite database, to analyze it again without the forge, or query it with SQL.
+///
+/// The store keeps the history of a single branch: the one of the first ingested query, and refuses any other. Past file contents aren't kept,
+/// they're read from the provider given to `with_contents_from`, if any. Only the ignore file is kept, as of the last
+/// ingestion, so the analysis excludes the same paths without the forge.
+pub struct HistoryStore {
+    connection: Mutex<Connection>,
+    contents: Option<Box<dyn ChangeHistoryProvider>>,
+}
+
+fn format_date(date: DateTime<Utc>) -> String {
+    date.to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+fn parse_date(date: &str) -> Result<DateTime<Utc>> {
+    Ok(DateTime::parse_from_rfc3339(date)?.with_timezone(&Utc))
+}
+
+impl HistoryStore {
+    /// Opens the store at `path`, creating it if it doesn't exist.
+    pub fn open(path: &Path) -> Result<Self> {
+        HistoryStore::with_connection(Connection::open(path)?)
+    }
+
+    /// A store living in memory only, mostly useful for tests.
+    pub fn in_memory() -> Result<Self> {
+        HistoryStore::with_connection(Connection::open_in_memory()?)
+    }
+
+    fn with_connection(connection: Connection) -> Result<Self> {
+        connection.execute_batch("PRAGMA foreign_keys = ON;")?;
+        connection.execute_batch(SCHEMA)?;
+        Ok(HistoryStore {
+            connection: Mutex::new(connection),
+            contents: None,
+        })
+    }
+
+    /// Reads the file contents from `history`, usually the forge the store was ingested from.
+    pub fn with_contents_from(mut self, history: Box<dyn ChangeHistoryProvider>) -> Self {
+        self.contents = Some(history);
+        self
+    }
+
+    /// Stores the commits of the query window that aren't stored yet, and returns how many were fetched. Only the
+    /// commits newer than the last ingestion are fetched when it covered the start of the window.
+    pub async fn ingest<P>(&self, history: &P, query: &HistoryQuery) -> Result<usize>
+    where
+        P: ChangeHistoryProvider + ?Sized,
+    {
+        self.check_git_ref(query.git_ref.as_deref())?;
+        let until = query.until.unwrap_or_else(Utc::now);
+        let since = match self.ingested_until(query.since, query.git_ref.as_deref())? {
+            Some(ingested_until) if ingested_until > query.since => ingested_until,
+            _ => query.since,
+        };
+        // Any analysis can run on the stored commits later, so they need everything
+        let fetch_query = HistoryQuery {
+            since,
+            until: Some(until),
+            changed_lines: true,
+            ..query.clone()
+        };
+
+        let listed_until = format_date(until);
+        let mut num_commits = 0;
+        let mut complete = true;
+        let mut commits = history.commits(&fetch_query).await?;
+        while let Some(commit) = commits.next().await {
+            match commit {
+                Ok(commit) => {
+                    self.store_commit(&commit, &listed_until, num_commits)?;
+                    num_commits += 1;
+                }
+                Err(error) => {
+                    query.failed_commits.record(&error);
+                    complete = false;
+                }
+            }
+        }
+
+        for filename in STORED_FILES {
+            self.store_file(history, filename, query.git_ref.as_deref())
+                .await?;
+        }
+
+        // Windows missing commits have to be fetched again next time
+        if complete {
+            self.connection.lock().unwrap().execute(
+                "INSERT INTO ingestions (since, until, git_ref) VALUES (?1, ?2, ?3)",
+                params![
+                    format_date(query.since.min(since)),
+                    listed_until,
+                    query.git_ref
+                ],
+            )?;
+        }
+        Ok(num_commits)
+    }
+
+    /// Keeps the current contents of `filename`, or forgets them if it doesn't exist anymore.
+    async fn store_file<P>(&self, history: &P, filename: &str, git_ref: Option<&str>) -> Result<()>
+    where
+        P: ChangeHistoryProvider + ?Sized,
+    {
+        let contents = history.file_contents(filename, git_ref).await.ok();
+        let connection = self.connection.lock().unwrap();
+        match contents {
+            Some(contents) => connection.execute(
+                "INSERT OR REPLACE INTO files (filename, contents) VALUES (?1, ?2)",
+                params![filename, contents],
+            )?,
+            None => {
+                connection.execute("DELETE FROM files WHERE filename = ?1", params![filename])?
+            }
+        };
+        Ok(())
+    }
+
+    /// Fails when the commits are asked for another ref than the ingested one, since the store keeps one branch.
+    fn check_git_ref(&self, git_ref: Option<&str>) -> Result<()> {
+        let other_ref: Option<Option<String>> = self
+            .connection
+            .lock()
+            .unwrap()
+            .query_row(
+                "SELECT git_ref FROM ingestions WHERE git_ref IS NOT ?1 LIMIT 1",
+                params![git_ref],
+                |row| row.get(0),
+            )
+            .optional()?;
+        match other_ref {
+            Some(other_ref) => Err(anyhow!(
+                "The history store keeps the commits of {}, not of {}: use another store for it",
+                other_ref.as_deref().unwrap_or("the default branch"),
+                git_ref.unwrap_or("the default branch")
+            )),
+            None => Ok(()),
+        }
+    }
+
+    /// The end of the window of `git_ref` ingested covering `date`, if any.
+    fn ingested_until(
+        &self,
+        date: DateTime<Utc>,
+        git_ref: Option<&str>,
+    ) -> Result<Option<DateTime<Utc>>> {
+        let date = format_date(date);
+        let ingested_until: Option<String> = self.connection.lock().unwrap().query_row(
+            "SELECT MAX(until) FROM ingestions WHERE since <= ?1 AND until >= ?1 AND git_ref IS ?2",
+            params![date, git_ref],
+            |row| row.get(0),
+        )?;
+        ingested_until.as_deref().map(parse_date).transpose()
+    }
+
+    /// Stores a commit listed at `listing_position` in the window ending at `listed_until`.
+    fn store_commit(
+        &self,
+        commit: &CommitChanges,
+        listed_until: &str,
+        listing_position: usize,
+    ) -> Result<()> {
+        let mut connection = self.connection.lock().unwrap();
+        let transaction = connection.transaction()?;
+        // Replacing the commit removes its file changes too
+        transaction.execute(
+            "INSERT OR REPLACE INTO commits
+                (sha, author, date, message, num_parents, listed_until, listing_position)
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            params![
+                commit.sha,
+                commit.author,
+                commit.date.map(format_date),
+                commit.message,
+                commit.num_parents,
+                listed_until,
+                listing_position
+            ],
+        )?;
+        for file in &commit.files {
+            transaction.execute(
+                "INSERT OR REPLACE INTO file_changes
+                    (sha, filename, previous_filename, additions, deletions, changed_lines)
+                    VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                params![
+                    commit.sha,
+                    file.filename,
+                    file.previous_filename,
+                    file.additions,
+                    file.deletions,
+                    serde_json::to_string(&file.changed_lines)?
+                ],
+            )?;
+        }
+        transaction.commit()?;
+        Ok(())
+    }
+
+    /// Records a measure of a file at a revision, replacing the previous one.
+    pub fn record_metric(&self, sha: &str, filename: &str, metric: &str, value: f64) -> Result<()> {
+        self.connection.lock().unwrap().execute(
+            "INSERT OR REPLACE INTO metrics (sha, filename, metric, value) VALUES (?1, ?2, ?3, ?4)",
+            params![sha, filename, metric, value],
+        )?;
+        Ok(())
+    }
+
+    /// A measure of a file at a revision, if recorded.
+    pub fn metric(&self, sha: &str, filename: &str, metric: &str) -> Result<Option<f64>> {
+        Ok(self
+            .connection
+            .lock()
+            .unwrap()
+            .query_row(
+                "SELECT value FROM metrics WHERE sha = ?1 AND filename = ?2 AND metric = ?3",
+                params![sha, filename, metric],
+                |row| row.get(0),
+            )
+            .optional()?)
+    }
+
+    fn load_commits(&self, query: &HistoryQuery) -> Result<Vec<CommitChanges>> {
+        let connection = self.connection.lock().unwrap();
+        let mut select = connection.prepare(
+            "SELECT commits.sha, author, date, message, num_parents,
+                    filename, previous_filename, additions, deletions, changed_lines
+                FROM commits LEFT JOIN file_changes ON file_changes.sha = commits.sha
+                WHERE date IS NULL OR (date >= ?1 AND (?2 IS NULL OR date <= ?2))
+                ORDER BY listed_until DESC, listing_position, date DESC, commits.sha, file_changes.rowid",
+        )?;
+
+        let mut rows = select.query(params![
+            format_date(query.since),
+            query.until.map(format_date)
+        ])?;
+        let mut commits: Vec<CommitChanges> = Vec::new();
+        while let Some(row) = rows.next()? {
+            let sha: String = row.get(0)?;
+            if commits.last().map(|commit| &commit.sha) != Some(&sha) {
+                let date: Option<String> = row.get(2)?;
+                commits.push(CommitChanges {
+                    sha,
+                    author: row.get(1)?,
+                    date: date.as_deref().map(parse_date).transpose()?,
+                    message: row.get(3)?,
+                    files: vec![],
+                    num_parents: row.get(4)?,
+                });
+            }
+            // Commits changing no files have a single row without any file
+            if let Some(filename) = row.get::<_, Option<String>>(5)? {
+                let changed_lines: String = row.get(9)?;
+                commits.last_mut().unwrap().files.push(FileChange {
+                    filename,
+                    previous_filename: row.get(6)?,
+                    additions: row.get(7)?,
+                    deletions: row.get(8)?,
+                    changed_lines: serde_json::from_str(&changed_lines)?,
+                });
+            }
+        }
+        Ok(commits)
+    }
+}
+
+#[async_trait::async_trait]
+impl ChangeHistoryProvider for HistoryStore {
+    async fn commits<'a>(&'a self, query: &HistoryQuery) -> Result<CommitStream<'a>> {
+        self.check_git_ref(query.git_ref.as_deref())?;
+        let commits = self.load_commits(query)?;
+        Ok(report_progress(
+            stream::iter(commits.into_iter().map(Ok)).boxed(),
+            query,
+        ))
+    }
+
+    /// Resolves the SHAs of the stored commits, abbreviated or not, as long as they match a single commit. Other refs
+    /// need the forge.
+    async fn commit_date(&self, git_ref: &str) -> Result<DateTime<Utc>> {
+        let matches = {
+            let connection = self.connection.lock().unwrap();
+            let mut select = connection.prepare(
+                "SELECT sha, date FROM commits WHERE substr(sha, 1, length(?1)) = ?1 LIMIT 2",
+            )?;
+            let matches = select
+                .query_map(params![git_ref], |row| {
+                    Ok((row.get::<_, String>(0)?, row.get::<_, Option<String>>(1)?))
+                })?
+                .collect::<rusqlite::Result<Vec<_>>>()?;
+            matches
+        };
+        match (matches.as_slice(), &self.contents) {
+            ([(_, Some(date))], _) => parse_date(date),
+            ([(sha, None)], _) => Err(anyhow!("The stored commit {sha} has no date")),
+            ([_, _, ..], _) => Err(anyhow!(
+                "{git_ref} is ambiguous, it abbreviates the SHAs of several stored commits"
+            )),
+            ([], Some(contents)) => contents.commit_date(git_ref).await,
+            ([], None) => Err(anyhow!("{git_ref} isn't the SHA of any stored commit")),
+        }
+    }
+
+    /// The stored commits are those of a single branch, whatever the git ref.
+    async fn last_commit_before(
+        &self,
+        git_ref: Option<&str>,
+        date: DateTime<Utc>,
+    ) -> Result<Option<String>> {
+        self.check_git_ref(git_ref)?;
+        Ok(self
+            .connection
+            .lock()
+            .unwrap()
+            .query_row(
+                "SELECT sha FROM commits WHERE date <= ?1
+                    ORDER BY listed_until DESC, listing_position, date DESC LIMIT 1",
+                params![format_date(date)],
+                |row| row.get(0),
+            )
+            .optional()?)
+    }
+
+    async fn file_contents(&self, path: &str, git_ref: Option<&str>) -> Result<String> {
+        if let Some(contents) = &self.contents {
+            return contents.file_contents(path, git_ref).await;
+        }
+        let stored: Option<String> = self
+            .connection
+            .lock()
+            .unwrap()
+            .query_row(
+                "SELECT contents FROM files WHERE filename = ?1",
+                params![path],
+                |row| row.get(0),
+            )
+            .optional()?;
+        stored.ok_or_else(|| anyhow!("The history store doesn't keep the contents of {path}"))
+    }
+
+    async fn issue_labels(&self, number: u64) -> Result<Vec<String>> {
+        match &self.contents {
+            Some(contents) => contents.issue_labels(number).await,
+            None => Err(anyhow!(
+                "The history store doesn't keep the labels of issue #{number}"
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn commit(sha: &str, day: u32, filenames: &[&str]) -> CommitChanges {
+        let commit = CommitChanges::changing(sha, filenames);
+        CommitChanges {
+            author: Some("Ada".into()),
+            date: Some(Utc.with_ymd_and_hms(2023, 1, day, 12, 0, 0).unwrap()),
+            message: format!("Commit {sha}"),
+            files: commit
+                .files
+                .into_iter()
+                .map(|file| FileChange {
+                    additions: 2,
+                    changed_lines: vec![1..=2],
+                    ..file
+                })
+                .collect(),
+            ..commit
+        }
+    }
+
+    fn january(day: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2023, 1, day, 0, 0, 0).unwrap()
+    }
+
+    #[tokio::test]
+    async fn load_the_stored_commits_of_the_window_from_the_newest() {
+        let store = HistoryStore::in_memory().unwrap();
+        let forge = vec![
+            commit("c3", 3, &["README.md"]),
+            commit("c2", 2, &["src/lib.rs", "README.md"]),
+            commit("c1", 1, &["src/lib.rs"]),
+        ];
+        let query = HistoryQuery::new(january(1), Some(january(31)));
+
+        assert_eq!(3, store.ingest(&forge, &query).await.unwrap());
+        let window = HistoryQuery::new(january(2), Some(january(31)));
+        let stored: Vec<_> = store
+            .commits(&window)
+            .await
+            .unwrap()
+            .map(|commit| commit.unwrap())
+            .collect()
+            .await;
+
+        assert_eq!(forge[..2].to_vec(), stored);
+        assert_eq!(
+            Some(january(2) + chrono::Duration::hours(12)),
+            store.commit_date("c2").await.ok()
+        );
+    }
+
+    #[tokio::test]
+    async fn look_up_the_last_stored_commit_before_a_date() {
+        let store = HistoryStore::in_memory().unwrap();
+        let forge = vec![
+            commit("c3", 3, &["README.md"]),
+            commit("c1", 1, &["src/lib.rs"]),
+        ];
+        store
+            .ingest(&forge, &HistoryQuery::new(january(1), Some(january(31))))
+            .await
+            .unwrap();
+
+        assert_eq!(
+            Some("c1".to_string()),
+            store.last_commit_before(None, january(3)).await.unwrap()
+        );
+        assert_eq!(
+            None,
+            store.last_commit_before(None, january(1)).await.unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn load_the_stored_commits_in_the_listing_order() {
+        let store = HistoryStore::in_memory().unwrap();
+        // A rebase can list a commit authored before its parent
+        let forge = vec![
+            commit("c2", 1, &["src/lib.rs"]),
+            commit("c1", 2, &["src/lib.rs"]),
+        ];
+        store
+            .ingest(&forge, &HistoryQuery::new(january(1), Some(january(10))))
+            .await
+            .unwrap();
+        let newer_forge = vec![commit("c3", 3, &[])];
+        store
+            .ingest(
+                &newer_forge,
+                &HistoryQuery::new(january(1), Some(january(20))),
+            )
+            .await
+            .unwrap();
+
+        let query = HistoryQuery::new(january(1), Some(january(31)));
+        let stored: Vec<_> = store
+            .commits(&query)
+            .await
+            .unwrap()
+            .map(|commit| commit.unwrap().sha)
+            .collect()
+            .await;
+
+        assert_eq!(vec!["c3", "c2", "c1"], stored);
+        let progress = query.progress.progress();
+        assert_eq!(
+            (3, 3, true),
+            (progress.listed, progress.fetched, progress.finished)
+        );
+        assert_eq!(
+            Some("c2".to_string()),
+            store.last_commit_before(None, january(3)).await.unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn refuse_ambiguous_abbreviated_shas() {
+        let store = HistoryStore::in_memory().unwrap();
+        let forge = vec![commit("abc2", 2, &[]), commit("abc1", 1, &[])];
+        store
+            .ingest(&forge, &HistoryQuery::new(january(1), Some(january(31))))
+            .await
+            .unwrap();
+
+        assert!(store.commit_date("abc").await.is_err());
+        assert_eq!(
+            january(1) + chrono::Duration::hours(12),
+            store.commit_date("abc1").await.unwrap()
+        );
+    }
+
+    struct ForgeWithIgnoreFile(Vec<CommitChanges>);
+
+    #[async_trait::async_trait]
+    impl ChangeHistoryProvider for ForgeWithIgnoreFile {
+        async fn commits<'a>(&'a self, query: &HistoryQuery) -> Result<CommitStream<'a>> {
+            self.0.commits(query).await
+        }
+
+        async fn commit_date(&self, git_ref: &str) -> Result<DateTime<Utc>> {
+            self.0.commit_date(git_ref).await
+        }
+
+        async fn file_contents(&self, path: &str, _git_ref: Option<&str>) -> Result<String> {
+            match path {
+                IGNORE_FILENAME => Ok("vendor/\n".into()),
+                _ => Err(anyhow!("No {path}")),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn read_the_ignore_file_without_the_forge() {
+        let path = std::env::temp_dir().join(format!("store-{}.db", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let forge = ForgeWithIgnoreFile(vec![commit("c1", 1, &["src/lib.rs"])]);
+        HistoryStore::open(&path)
+            .unwrap()
+            .ingest(&forge, &HistoryQuery::new(january(1), Some(january(31))))
+            .await
+            .unwrap();
+
+        let store = HistoryStore::open(&path).unwrap();
+
+        assert_eq!(
+            "vendor/\n",
+            store.file_contents(IGNORE_FILENAME, None).await.unwrap()
+        );
+        assert!(store.file_contents("src/lib.rs", None).await.is_err());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn ingest_only_the_commits_after_the_last_ingestion() {
+        let store = HistoryStore::in_memory().unwrap();
+        let query = HistoryQuery::new(january(1), Some(january(10)));
+        store
+            .ingest(&vec![commit("c1", 1, &["src/lib.rs"])], &query)
+            .await
+            .unwrap();
+
+        // Recorded histories ignore the window, so only the window of the ingestion tells what was fetched
+        let later_query = HistoryQuery::new(january(1), Some(january(20)));
+        let forge = vec![commit("c2", 15, &["src/lib.rs"])];
+        store.ingest(&forge, &later_query).await.unwrap();
+
+        assert_eq!(
+            Some(january(20)),
+            store.ingested_until(january(1), None).unwrap()
+        );
+        assert_eq!(
+            Some(january(20)),
+            store.ingested_until(january(15), None).unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn refuse_another_branch_than_the_ingested_one() {
+        let store = HistoryStore::in_memory().unwrap();
+        let forge = vec![commit("c1", 1, &["src/lib.rs"])];
+        let query = HistoryQuery::new(january(1), Some(january(10)));
+        store.ingest(&forge, &query).await.unwrap();
+
+        let branch_query = HistoryQuery {
+            git_ref: Some("feature".into()),
+            ..query.clone()
+        };
+
+        assert!(store.ingest(&forge, &branch_query).await.is_err());
+        assert!(store.commits(&branch_query).await.is_err());
+        assert!(store
+            .last_commit_before(Some("feature"), january(10))
+            .await
+            .is_err());
+        assert_eq!(
+            None,
+            store.ingested_until(january(1), Some("feature")).unwrap()
+        );
+        assert!(store.commits(&query).await.is_ok());
+    }
+
+    #[test]
+    fn record_metrics_per_revision() {
+        let store = HistoryStore::in_memory().unwrap();
+
+        store
+            .record_metric("c1", "src/lib.rs", "complexity", 12.0)
+            .unwrap();
+        store
+            .record_metric("c1", "src/lib.rs", "complexity", 14.0)
+            .unwrap();
+
+        assert_eq!(
+            Some(14.0),
+            store.metric("c1", "src/lib.rs", "complexity").unwrap()
+        );
+        assert_eq!(
+            None,
+            store.metric("c2", "src/lib.rs", "complexity").unwrap()
+        );
+    }
+}
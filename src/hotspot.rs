@@ -0,0 +1,278 @@
+use crate::{compute_file_complexities, TopComplexities};
+use clap::ValueEnum;
+use complexity_radar::history::ChangeHistoryProvider;
+use complexity_radar::{top_k_by, ChangedFileStats, RankBy};
+
+// Files above this share of the highest change frequency (or complexity) are hot (or complex)
+const QUADRANT_THRESHOLD: f64 = 0.5;
+// Below this share of the commits, the main developer of a file doesn't really own it
+const MAIN_DEVELOPER_MIN_SHARE: f64 = 0.5;
+// Above this fragmentation, the commits of a file are spread among many authors
+const FRAGMENTATION_THRESHOLD: f64 = 0.5;
+
+/// How to turn the complexities of the functions of a file into the complexity of the file.
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ComplexityAggregate {
+    /// Sum of the complexities of all the functions
+    #[default]
+    Sum,
+    /// Complexity of the most complex function
+    Max,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Quadrant {
+    HotAndComplex,
+    HotAndSimple,
+    ColdAndComplex,
+    ColdAndSimple,
+}
+
+impl std::fmt::Display for Quadrant {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Quadrant::HotAndComplex => "hot & complex",
+            Quadrant::HotAndSimple => "hot & simple",
+            Quadrant::ColdAndComplex => "cold & complex",
+            Quadrant::ColdAndSimple => "cold & simple",
+        })
+    }
+}
+
+/// A file scored by how often it changes and how complex it is.
+pub struct Hotspot {
+    pub top_complexities: TopComplexities,
+    pub complexity: u32,
+    /// Change frequency times complexity, both normalized to 0..1, so the hottest and most complex file scores 1
+    pub score: f64,
+    pub quadrant: Quadrant,
+}
+
+impl Hotspot {
+    /// Whether the file is hot and complex, and changed by many authors without any of them owning it.
+    pub fn has_fragmented_ownership(&self) -> bool {
+        self.quadrant == Quadrant::HotAndComplex
+            && self
+                .top_complexities
+                .changes
+                .ownership()
+                .is_some_and(|ownership| {
+                    ownership.commit_share < MAIN_DEVELOPER_MIN_SHARE
+                        && ownership.fragmentation > FRAGMENTATION_THRESHOLD
+                })
+    }
+}
+
+pub fn file_complexity(top_complexities: &TopComplexities, aggregate: ComplexityAggregate) -> u32 {
+    let function_complexities = top_complexities
+        .function_complexities
+        .iter()
+        .map(|function_complexity| function_complexity.cognitive_complexity_value as u32);
+    match aggregate {
+        ComplexityAggregate::Sum => function_complexities.sum(),
+        ComplexityAggregate::Max => function_complexities.max().unwrap_or(0),
+    }
+}
+
+/// Scores the files by change frequency, measured as `rank_by` says, and complexity, and returns the
+/// `num_hotspots` with the highest score, sorted by score.
+pub fn rank_hotspots(
+    files: Vec<TopComplexities>,
+    rank_by: RankBy,
+    aggregate: ComplexityAggregate,
+    num_hotspots: usize,
+) -> Vec<Hotspot> {
+    let change_frequency = |top_complexities: &TopComplexities| {
+        top_complexities.changes.rank_value(rank_by).unwrap_or(0.0)
+    };
+    let max_change_frequency = files.iter().map(change_frequency).fold(0.0, f64::max);
+    let max_complexity = files
+        .iter()
+        .map(|top_complexities| file_complexity(top_complexities, aggregate))
+        .max()
+        .unwrap_or(0);
+
+    let hotspots = files.into_iter().map(|top_complexities| {
+        let complexity = file_complexity(&top_complexities, aggregate);
+        let change_frequency = normalize(change_frequency(&top_complexities), max_change_frequency);
+        let normalized_complexity = normalize(complexity as f64, max_complexity as f64);
+        let quadrant = match (
+            change_frequency >= QUADRANT_THRESHOLD,
+            normalized_complexity >= QUADRANT_THRESHOLD,
+        ) {
+            (true, true) => Quadrant::HotAndComplex,
+            (true, false) => Quadrant::HotAndSimple,
+            (false, true) => Quadrant::ColdAndComplex,
+            (false, false) => Quadrant::ColdAndSimple,
+        };
+        Hotspot {
+            top_complexities,
+            complexity,
+            score: change_frequency * normalized_complexity,
+            quadrant,
+        }
+    });
+    top_k_by(hotspots, num_hotspots, |hotspot1, hotspot2| {
+        hotspot1.score.total_cmp(&hotspot2.score)
+    })
+}
+
+/// Computes the complexity of the candidate files and returns the `num_hotspots` with the highest hotspot score.
+/// Files we can't compute the complexity of (deleted, or in other languages) can't be hotspots.
+pub async fn find_hotspots(
+    history: &dyn ChangeHistoryProvider,
+    candidates: Vec<ChangedFileStats>,
+    git_ref: Option<&str>,
+    rank_by: RankBy,
+    aggregate: ComplexityAggregate,
+    num_hotspots: usize,
+) -> Vec<Hotspot> {
+    let mut files = Vec::new();
+    for changes in candidates {
+        if let Ok(function_complexities) =
+            compute_file_complexities(history, &changes.filename, git_ref).await
+        {
+            files.push(TopComplexities {
+                changes,
+                function_complexities,
+            });
+        }
+    }
+    rank_hotspots(files, rank_by, aggregate, num_hotspots)
+}
+
+fn normalize(value: f64, max: f64) -> f64 {
+    match max > 0.0 {
+        true => value / max,
+        false => 0.0,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::complexity::FunctionComplexity;
+    use complexity_radar::history::{CommitChanges, FileChange, HistoryQuery};
+    use complexity_radar::ownership::AuthorChanges;
+    use complexity_radar::TopChangedFilesExt;
+
+    fn top_complexities(filename: &str, num_commits: u32, complexities: &[u16]) -> TopComplexities {
+        TopComplexities {
+            changes: ChangedFileStats {
+                filename: filename.into(),
+                num_commits,
+                lines_changed: 0,
+                num_lines: None,
+                ..Default::default()
+            },
+            function_complexities: complexities
+                .iter()
+                .map(|complexity| FunctionComplexity {
+                    function: "function".into(),
+                    cognitive_complexity_value: *complexity,
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn rank_files_by_hotspot_score_and_classify_them_in_quadrants() {
+        let files = vec![
+            top_complexities("hot_simple.rs", 10, &[1]),
+            top_complexities("cold_complex.rs", 1, &[10, 10]),
+            top_complexities("hot_complex.rs", 8, &[5, 10]),
+            top_complexities("cold_simple.rs", 2, &[]),
+        ];
+
+        let hotspots = rank_hotspots(files, RankBy::Commits, ComplexityAggregate::Sum, 10);
+
+        let ranking: Vec<_> = hotspots
+            .iter()
+            .map(|hotspot| {
+                (
+                    hotspot.top_complexities.changes.filename.as_str(),
+                    hotspot.quadrant,
+                )
+            })
+            .collect();
+        assert_eq!(
+            vec![
+                ("hot_complex.rs", Quadrant::HotAndComplex),
+                ("cold_complex.rs", Quadrant::ColdAndComplex),
+                ("hot_simple.rs", Quadrant::HotAndSimple),
+                ("cold_simple.rs", Quadrant::ColdAndSimple),
+            ],
+            ranking
+        );
+        assert!((hotspots[0].score - 0.6).abs() < 1e-9);
+    }
+
+    #[test]
+    fn flag_hot_and_complex_files_without_a_clear_owner() {
+        let author_changes = |author: &str, num_commits| AuthorChanges {
+            author: author.into(),
+            num_commits,
+            ..Default::default()
+        };
+        let mut owned = top_complexities("owned.rs", 10, &[10]);
+        owned.changes.authors = vec![author_changes("Ada", 8), author_changes("Grace", 2)];
+        let mut fragmented = top_complexities("fragmented.rs", 10, &[10]);
+        fragmented.changes.authors = ["Ada", "Grace", "Linus", "Ken", "Barbara"]
+            .iter()
+            .map(|author| author_changes(author, 2))
+            .collect();
+
+        let hotspots = rank_hotspots(
+            vec![owned, fragmented],
+            RankBy::Commits,
+            ComplexityAggregate::Sum,
+            10,
+        );
+
+        assert!(!hotspots[0].has_fragmented_ownership());
+        assert!(hotspots[1].has_fragmented_ownership());
+    }
+
+    #[tokio::test]
+    async fn leave_out_the_files_deleted_since_they_changed() {
+        let history: Vec<_> = ["src/deleted.rs", "src/hotspot.rs", "src/deleted.rs"]
+            .iter()
+            .map(|filename| CommitChanges {
+                files: vec![FileChange {
+                    filename: filename.to_string(),
+                    ..Default::default()
+                }],
+                ..Default::default()
+            })
+            .collect();
+        let query = HistoryQuery::default();
+        let candidates = history
+            .get_top_changed_file_stats(10, &query, RankBy::Commits)
+            .await
+            .unwrap();
+
+        let hotspots = find_hotspots(
+            &history,
+            candidates,
+            None,
+            RankBy::Commits,
+            ComplexityAggregate::Sum,
+            10,
+        )
+        .await;
+
+        let filenames: Vec<_> = hotspots
+            .iter()
+            .map(|hotspot| hotspot.top_complexities.changes.filename.as_str())
+            .collect();
+        assert_eq!(vec!["src/hotspot.rs"], filenames);
+    }
+
+    #[test]
+    fn aggregate_the_function_complexities_of_a_file() {
+        let file = top_complexities("lib.rs", 1, &[3, 7, 2]);
+
+        assert_eq!(12, file_complexity(&file, ComplexityAggregate::Sum));
+        assert_eq!(7, file_complexity(&file, ComplexityAggregate::Max));
+    }
+}
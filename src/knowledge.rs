@@ -0,0 +1,198 @@
+use crate::ownership::{AuthorChanges, FileOwnership};
+use crate::{top_k_by, ChangedFileStats};
+use chrono::{DateTime, Utc};
+use itertools::Itertools;
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::path::Path;
+
+/// Share of the changes of a file or directory its key authors account for.
+pub const KEY_AUTHORS_SHARE: f64 = 0.8;
+
+/// How the knowledge of a file or directory is spread among its authors.
+#[derive(Debug, Clone, PartialEq)]
+pub struct KnowledgeDistribution {
+    /// File or directory
+    pub path: String,
+    /// Commits changing the file, or every file of the directory
+    pub num_changes: u32,
+    pub num_authors: usize,
+    /// Fewest authors accounting for `KEY_AUTHORS_SHARE` of the changes
+    pub num_key_authors: usize,
+    pub main_developer: Option<String>,
+    /// The main developer hasn't committed anything since the inactivity limit
+    pub main_developer_inactive: bool,
+}
+
+/// When every author last committed, to any of the files.
+pub fn last_activity(files: &[ChangedFileStats]) -> HashMap<String, DateTime<Utc>> {
+    let mut last_activity: HashMap<String, DateTime<Utc>> = HashMap::new();
+    for author_changes in files.iter().flat_map(|file| &file.authors) {
+        if let Some(last_change) = author_changes.last_change {
+            let last_commit = last_activity
+                .entry(author_changes.author.clone())
+                .or_insert(last_change);
+            *last_commit = (*last_commit).max(last_change);
+        }
+    }
+    last_activity
+}
+
+fn num_key_authors(authors: &[AuthorChanges]) -> usize {
+    let total_commits: u32 = authors.iter().map(|author| author.num_commits).sum();
+    let mut key_commits = 0;
+    authors
+        .iter()
+        .map(|author| author.num_commits)
+        .sorted_by(|commits1, commits2| commits2.cmp(commits1))
+        .take_while(|num_commits| {
+            let is_key = (key_commits as f64) < KEY_AUTHORS_SHARE * total_commits as f64;
+            key_commits += num_commits;
+            is_key
+        })
+        .count()
+}
+
+/// Knowledge distribution of a file or directory. Main developers whose last commit is older than
+/// `inactive_since` are flagged as inactive.
+pub fn knowledge_of(
+    path: &str,
+    authors: &[AuthorChanges],
+    last_activity: &HashMap<String, DateTime<Utc>>,
+    inactive_since: DateTime<Utc>,
+) -> KnowledgeDistribution {
+    let main_developer = FileOwnership::of(authors).map(|ownership| ownership.main_developer);
+    let main_developer_inactive = main_developer
+        .as_ref()
+        .and_then(|main_developer| last_activity.get(main_developer))
+        .is_some_and(|last_commit| *last_commit < inactive_since);
+
+    KnowledgeDistribution {
+        path: path.to_string(),
+        num_changes: authors.iter().map(|author| author.num_commits).sum(),
+        num_authors: authors.len(),
+        num_key_authors: num_key_authors(authors),
+        main_developer,
+        main_developer_inactive,
+    }
+}
+
+/// Knowledge distribution of the `num_directories` directories with the most changes, counting only the files
+/// directly in them, sorted by changes.
+pub fn knowledge_of_directories(
+    files: &[ChangedFileStats],
+    last_activity: &HashMap<String, DateTime<Utc>>,
+    inactive_since: DateTime<Utc>,
+    num_directories: usize,
+) -> Vec<KnowledgeDistribution> {
+    // The authors of every directory, with the position of every one of them
+    let mut directory_authors: HashMap<String, (Vec<AuthorChanges>, HashMap<&str, usize>)> =
+        HashMap::new();
+    for file in files {
+        let directory = match Path::new(&file.filename).parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent.to_string_lossy().into_owned(),
+            _ => ".".to_string(),
+        };
+        let (authors, author_index) = directory_authors.entry(directory).or_default();
+        for author_changes in &file.authors {
+            match author_index.entry(&author_changes.author) {
+                Entry::Occupied(position) => {
+                    let changes = &mut authors[*position.get()];
+                    changes.num_commits += author_changes.num_commits;
+                    changes.lines_changed += author_changes.lines_changed;
+                    changes.last_change = changes.last_change.max(author_changes.last_change);
+                }
+                Entry::Vacant(position) => {
+                    position.insert(authors.len());
+                    authors.push(author_changes.clone());
+                }
+            }
+        }
+    }
+
+    let directories = directory_authors.iter().map(|(directory, (authors, _))| {
+        knowledge_of(directory, authors, last_activity, inactive_since)
+    });
+    top_k_by(directories, num_directories, |knowledge1, knowledge2| {
+        knowledge1
+            .num_changes
+            .cmp(&knowledge2.num_changes)
+            .then_with(|| knowledge2.path.cmp(&knowledge1.path))
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn author_changes(author: &str, num_commits: u32, last_change_day: u32) -> AuthorChanges {
+        AuthorChanges {
+            author: author.into(),
+            num_commits,
+            lines_changed: 0,
+            last_change: Some(
+                Utc.with_ymd_and_hms(2023, 1, last_change_day, 0, 0, 0)
+                    .unwrap(),
+            ),
+        }
+    }
+
+    fn changed_file(filename: &str, authors: Vec<AuthorChanges>) -> ChangedFileStats {
+        ChangedFileStats {
+            filename: filename.into(),
+            num_commits: authors.iter().map(|author| author.num_commits).sum(),
+            authors,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn count_the_authors_accounting_for_most_of_the_changes() {
+        let authors = vec![
+            author_changes("Grace", 2, 1),
+            author_changes("Ada", 7, 1),
+            author_changes("Linus", 1, 1),
+        ];
+
+        assert_eq!(2, num_key_authors(&authors));
+        assert_eq!(1, num_key_authors(&authors[1..2]));
+        assert_eq!(0, num_key_authors(&[]));
+    }
+
+    #[test]
+    fn flag_directories_whose_main_developer_is_gone() {
+        let files = vec![
+            changed_file("src/parser.rs", vec![author_changes("Ada", 5, 3)]),
+            changed_file(
+                "src/lexer.rs",
+                vec![author_changes("Ada", 1, 2), author_changes("Grace", 2, 20)],
+            ),
+            changed_file("README.md", vec![author_changes("Grace", 1, 25)]),
+        ];
+        let inactive_since = Utc.with_ymd_and_hms(2023, 1, 10, 0, 0, 0).unwrap();
+
+        let directories =
+            knowledge_of_directories(&files, &last_activity(&files), inactive_since, 10);
+
+        let expected = vec![
+            KnowledgeDistribution {
+                path: "src".into(),
+                num_changes: 8,
+                num_authors: 2,
+                num_key_authors: 2,
+                main_developer: Some("Ada".into()),
+                main_developer_inactive: true,
+            },
+            KnowledgeDistribution {
+                path: ".".into(),
+                num_changes: 1,
+                num_authors: 1,
+                num_key_authors: 1,
+                main_developer: Some("Grace".into()),
+                main_developer_inactive: false,
+            },
+        ];
+        assert_eq!(expected, directories);
+    }
+}
@@ -1,99 +1,382 @@
+pub mod coupling;
+pub mod defects;
+pub mod history;
+pub mod knowledge;
+pub mod ownership;
+
 use anyhow::Result;
 
-use chrono::{Duration, Utc};
-use futures::stream;
+use chrono::{DateTime, Utc};
+use defects::BugFixClassifier;
+use futures::stream::BoxStream;
 use futures_util::StreamExt;
-use itertools::Itertools;
-use octocrab::models::repos::RepoCommit;
+use history::{ChangeHistoryProvider, CommitChanges, FileChange, HistoryQuery, RenameTracker};
 pub use octocrab::Octocrab;
-use std::ops::Sub;
+use ownership::{AuthorChanges, FileOwnership};
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::sync::Arc;
 
 //pub type ChangedFileCounts = std::collections::BTreeMap<std::string::String, u32>;
 pub type ChangedFileCounts = Vec<(std::string::String, u32)>;
 
+/// How to rank the most changed files.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum RankBy {
+    /// Number of commits changing the file
+    #[default]
+    Commits,
+    /// Lines added plus lines deleted by all the commits
+    LinesChanged,
+    /// Lines changed divided by the current size of the file, in lines
+    RelativeChurn,
+}
+
+/// How much a file changed in the analysis window, and who changed it.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct ChangedFileStats {
+    pub filename: String,
+    pub num_commits: u32,
+    pub lines_changed: u32,
+    /// Size of the file at the analyzed ref, `None` if it doesn't exist anymore
+    pub num_lines: Option<u32>,
+    /// Changes of every known author, in the order they are first seen
+    pub authors: Vec<AuthorChanges>,
+    /// Commits fixing bugs, only counted when asked for
+    pub num_bug_fixes: u32,
+}
+
+impl ChangedFileStats {
+    pub fn ownership(&self) -> Option<FileOwnership> {
+        FileOwnership::of(&self.authors)
+    }
+
+    pub fn relative_churn(&self) -> Option<f64> {
+        self.num_lines
+            .map(|num_lines| self.lines_changed as f64 / num_lines.max(1) as f64)
+    }
+
+    /// Share of the commits changing the file that fixed bugs.
+    pub fn bug_fix_ratio(&self) -> f64 {
+        self.num_bug_fixes as f64 / self.num_commits.max(1) as f64
+    }
+
+    /// Bug fixes per thousand lines of the file, `None` if it doesn't exist anymore.
+    pub fn defect_density(&self) -> Option<f64> {
+        self.num_lines
+            .map(|num_lines| self.num_bug_fixes as f64 * 1000.0 / num_lines.max(1) as f64)
+    }
+
+    /// The measure `rank_by` ranks the files by, `None` if it can't be computed for this file.
+    pub fn rank_value(&self, rank_by: RankBy) -> Option<f64> {
+        match rank_by {
+            RankBy::Commits => Some(self.num_commits as f64),
+            RankBy::LinesChanged => Some(self.lines_changed as f64),
+            RankBy::RelativeChurn => self.relative_churn(),
+        }
+    }
+}
+
 #[async_trait::async_trait]
 pub trait TopChangedFilesExt {
     async fn get_top_changed_files(
         &self,
         num_of_files: usize,
-        owner: &str,
-        repo: &str,
+        query: &HistoryQuery,
     ) -> Result<ChangedFileCounts>;
+
+    /// Like `get_top_changed_files`, but ranking the files by any of the churn measures and telling all of them.
+    async fn get_top_changed_file_stats(
+        &self,
+        num_of_files: usize,
+        query: &HistoryQuery,
+        rank_by: RankBy,
+    ) -> Result<Vec<ChangedFileStats>>;
+}
+
+/// Commits in the analysis window, with their files under their current path and only the files we count.
+pub(crate) async fn tracked_commits<'a, P>(
+    history: &'a P,
+    query: &'a HistoryQuery,
+) -> Result<BoxStream<'a, CommitChanges>>
+where
+    P: ChangeHistoryProvider + ?Sized,
+{
+    Ok(tracked_commits_with_paths(history, query)
+        .await?
+        .map(|(commit, _)| commit)
+        .boxed())
+}
+
+/// Like `tracked_commits`, together with the path every file of the commit had at that commit, to read it there.
+pub async fn tracked_commits_with_paths<'a, P>(
+    history: &'a P,
+    query: &'a HistoryQuery,
+) -> Result<BoxStream<'a, (CommitChanges, Vec<String>)>>
+where
+    P: ChangeHistoryProvider + ?Sized,
+{
+    let (path_filter, commit_filter) = (&query.path_filter, &query.commit_filter);
+    let failed_commits = &query.failed_commits;
+    Ok(history
+        .commits(query)
+        .await?
+        .filter_map(move |commit| async move {
+            commit.map_err(|error| failed_commits.record(&error)).ok()
+        })
+        .scan(RenameTracker::default(), |rename_tracker, commit| {
+            let paths_at_commit: Vec<_> = commit
+                .files
+                .iter()
+                .map(|file_change| file_change.filename.clone())
+                .collect();
+            futures::future::ready(Some((rename_tracker.follow(commit), paths_at_commit)))
+        })
+        // Left out commits are still followed, so the renames they make aren't missed
+        .filter(move |(commit, _)| futures::future::ready(commit_filter.is_included(commit)))
+        .map(move |(commit, paths_at_commit)| {
+            let (files, paths_at_commit) = commit
+                .files
+                .into_iter()
+                .zip(paths_at_commit)
+                .filter(|(file_change, _)| path_filter.is_included(&file_change.filename))
+                .unzip();
+            (CommitChanges { files, ..commit }, paths_at_commit)
+        })
+        .boxed())
+}
+
+/// Aggregates the changes of every file in the analysis window, in the order they are first seen.
+pub async fn count_file_changes<P>(
+    history: &P,
+    query: &HistoryQuery,
+) -> Result<Vec<ChangedFileStats>>
+where
+    P: ChangeHistoryProvider + ?Sized,
+{
+    aggregate_file_changes(history, query, None).await
+}
+
+/// Like `count_file_changes`, also counting the bug fixes if a classifier is given.
+pub(crate) async fn aggregate_file_changes<P>(
+    history: &P,
+    query: &HistoryQuery,
+    bug_fix_classifier: Option<&BugFixClassifier>,
+) -> Result<Vec<ChangedFileStats>>
+where
+    P: ChangeHistoryProvider + ?Sized,
+{
+    let changed_files = tracked_commits(history, query)
+        .await?
+        .then(|commit| async move {
+            let is_bug_fix = match bug_fix_classifier {
+                Some(classifier) => classifier.is_bug_fix(history, &commit).await,
+                None => false,
+            };
+            (commit, is_bug_fix)
+        })
+        .flat_map(|(commit, is_bug_fix)| {
+            // Shared by every file of the commit instead of copied
+            let author: Option<Arc<str>> = commit.author.map(Arc::from);
+            let date = commit.date;
+            futures::stream::iter(
+                commit
+                    .files
+                    .into_iter()
+                    .map(move |file_change| (author.clone(), date, is_bug_fix, file_change)),
+            )
+        })
+        .fold(
+            FileChangeCounter::default(),
+            |mut counter, (author, date, is_bug_fix, file_change)| async move {
+                counter.count(file_change, author, date, is_bug_fix);
+                counter
+            },
+        )
+        .await;
+
+    Ok(changed_files.into_stats())
+}
+
+/// Changes of every file, in the order they are first seen. Files and their authors are indexed, so that counting a
+/// change doesn't depend on how many files or authors there are.
+#[derive(Default)]
+struct FileChangeCounter {
+    changed_files: Vec<ChangedFileStats>,
+    /// Position of every file in `changed_files`. The paths are only kept here until they're moved into the stats
+    index: HashMap<String, usize>,
+    /// Position of every author in the `authors` of the stats of every file they changed
+    author_index: HashMap<(usize, Arc<str>), usize>,
+}
+
+impl FileChangeCounter {
+    fn count(
+        &mut self,
+        file_change: FileChange,
+        author: Option<Arc<str>>,
+        date: Option<DateTime<Utc>>,
+        is_bug_fix: bool,
+    ) {
+        // We want to measure how frequency a filename is changed, instead of how many changes the file has for a
+        // specific commit. That's why we count how many commits have changes for a specific file.
+        let lines_changed = file_change.lines_changed();
+        let new_position = self.changed_files.len();
+        let position = *self
+            .index
+            .entry(file_change.filename)
+            .or_insert(new_position);
+        if position == new_position {
+            self.changed_files.push(ChangedFileStats::default());
+        }
+        let stats = &mut self.changed_files[position];
+        stats.num_commits += 1;
+        stats.lines_changed += lines_changed;
+        stats.num_bug_fixes += u32::from(is_bug_fix);
+
+        if let Some(author) = author {
+            let new_author_position = stats.authors.len();
+            let author_position = *self
+                .author_index
+                .entry((position, author.clone()))
+                .or_insert(new_author_position);
+            if author_position == new_author_position {
+                stats.authors.push(AuthorChanges {
+                    author: author.to_string(),
+                    num_commits: 0,
+                    lines_changed: 0,
+                    last_change: date,
+                });
+            }
+            let author_changes = &mut stats.authors[author_position];
+            author_changes.num_commits += 1;
+            author_changes.lines_changed += lines_changed;
+            author_changes.last_change = author_changes.last_change.max(date);
+        }
+    }
+
+    fn into_stats(self) -> Vec<ChangedFileStats> {
+        let mut changed_files = self.changed_files;
+        for (filename, position) in self.index {
+            changed_files[position].filename = filename;
+        }
+        changed_files
+    }
+}
+
+/// The `k` greatest items by `compare`, from the greatest, keeping the order of the items that compare equal. The
+/// top `k` are selected in linear time and only they are sorted, so it takes `O(n + k log k)` comparisons instead
+/// of sorting them all.
+pub fn top_k_by<T>(
+    items: impl IntoIterator<Item = T>,
+    k: usize,
+    compare: impl Fn(&T, &T) -> Ordering,
+) -> Vec<T> {
+    if k == 0 {
+        return vec![];
+    }
+    // Ties are broken by the position of the items, so they keep their order
+    let rank = |(position1, item1): &(usize, T), (position2, item2): &(usize, T)| {
+        compare(item2, item1).then(position1.cmp(position2))
+    };
+    let mut ranked: Vec<_> = items.into_iter().enumerate().collect();
+    if k < ranked.len() {
+        ranked.select_nth_unstable_by(k - 1, rank);
+        ranked.truncate(k);
+    }
+    ranked.sort_unstable_by(rank);
+    ranked.into_iter().map(|(_, item)| item).collect()
+}
+
+/// Fills in the size of the files we don't know yet, reading them at the analyzed ref.
+pub(crate) async fn measure_file_sizes<P>(
+    history: &P,
+    changed_files: &mut [ChangedFileStats],
+    git_ref: Option<&str>,
+) where
+    P: ChangeHistoryProvider + ?Sized,
+{
+    for stats in changed_files
+        .iter_mut()
+        .filter(|stats| stats.num_lines.is_none())
+    {
+        // Files deleted since then can't be read, they just have no size
+        stats.num_lines = history
+            .file_contents(&stats.filename, git_ref)
+            .await
+            .ok()
+            .map(|contents| contents.lines().count() as u32);
+    }
 }
 
 #[async_trait::async_trait]
-impl TopChangedFilesExt for Octocrab {
+impl<P> TopChangedFilesExt for P
+where
+    P: ChangeHistoryProvider + ?Sized,
+{
     async fn get_top_changed_files(
         &self,
         number_of_files: usize,
-        owner: &str,
-        repo: &str,
+        query: &HistoryQuery,
     ) -> Result<ChangedFileCounts> {
-        let commits_stream = self
-            .repos(owner, repo)
-            .list_commits()
-            .since(Utc::now().sub(Duration::days(365)))
-            .send()
-            .await?
-            .into_stream(&self);
-
-        let changed_files: ChangedFileCounts = commits_stream
-            .filter_map(
-                |repo_commit| async move { repo_commit.ok().map(|repo_commit| repo_commit) },
-            )
-            .filter_map(|repo_commit| async move {
-                self.get(repo_commit.url, None::<&()>).await.ok() as Option<RepoCommit>
-            })
-            .flat_map(|commit| stream::iter(commit.files))
-            .flat_map(|diff_entries| stream::iter(diff_entries))
-            .fold(
-                Vec::new(),
-                |mut interim_changed_files, diff_entry| async move {
-                    // We want to measure how frequency a filename is changed, instead of how many changes the file has
-                    // for a specific commit. That's why we count how many commits have changes for a specific file.
-                    interim_changed_files
-                        .iter_mut()
-                        .find(|(filename, _)| *filename == diff_entry.filename)
-                        .map(|existing_entry| existing_entry.1 += 1)
-                        .or_else(|| Some(interim_changed_files.push((diff_entry.filename, 1))));
-                    interim_changed_files
-                },
-            )
-            .await
-            .into_iter()
-            .sorted_by(|(_, b1), (_, b2)| b2.cmp(b1))
-            .take(number_of_files)
-            .collect();
+        let changed_files: ChangedFileCounts = top_k_by(
+            count_file_changes(self, query).await?,
+            number_of_files,
+            |b1, b2| b1.num_commits.cmp(&b2.num_commits),
+        )
+        .into_iter()
+        .map(|stats| (stats.filename, stats.num_commits))
+        .collect();
 
         Ok(changed_files)
     }
+
+    async fn get_top_changed_file_stats(
+        &self,
+        number_of_files: usize,
+        query: &HistoryQuery,
+        rank_by: RankBy,
+    ) -> Result<Vec<ChangedFileStats>> {
+        let git_ref = query.git_ref.as_deref();
+        let mut changed_files = count_file_changes(self, query).await?;
+        // Ranking by relative churn needs the size of every file, otherwise we only measure the top ones
+        if rank_by == RankBy::RelativeChurn {
+            measure_file_sizes(self, &mut changed_files, git_ref).await;
+        }
+        let mut top_changed_files = top_k_by(changed_files, number_of_files, |b1, b2| {
+            b1.rank_value(rank_by)
+                .partial_cmp(&b2.rank_value(rank_by))
+                .unwrap_or(Ordering::Equal)
+        });
+        if rank_by != RankBy::RelativeChurn {
+            measure_file_sizes(self, &mut top_changed_files, git_ref).await;
+        }
+
+        Ok(top_changed_files)
+    }
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
 
-    use std::fs;
-    use std::io;
-
-    use octocrab::models::repos::{RepoCommit, RepoCommitPage};
-    use octocrab::models::Author;
-    use serde::{Deserialize, Serialize};
+    use chrono::Utc;
+    use futures_util::TryStreamExt;
+    use history::{
+        BitbucketHistory, CommitCache, CommitChanges, CommitFilter, FileChange, ForgeFixture,
+        GitHubGraphQlHistory, GitHubHistory, GitLabHistory, GiteaHistory, PathFilter,
+        RecordedResponses, WithForgeOptions,
+    };
     use serde_json::json;
     use wiremock::{
-        matchers::{method, path, path_regex},
+        matchers::{body_partial_json, header, method, path, path_regex, query_param},
         Mock, MockServer, ResponseTemplate,
     };
 
-    #[derive(Serialize, Deserialize)]
-    struct FakePage<T> {
-        items: Vec<T>,
-    }
-
+    /// Answers the requests nothing else answers with a 404, which isn't retried.
     pub async fn setup_error_handler(mock_server: &MockServer, message: &str) {
         Mock::given(method("GET"))
             .and(path_regex(".*"))
-            .respond_with(ResponseTemplate::new(500).set_body_json(json!( {
+            .respond_with(ResponseTemplate::new(404).set_body_json(json!( {
                 "documentation_url": "",
                 "errors": None::<Vec<serde_json::Value>>,
                 "message": message,
@@ -102,51 +385,852 @@ mod test {
             .await;
     }
 
-    async fn setup_api(template: ResponseTemplate) -> MockServer {
+    fn repo_commit_json(base_url: &str, commit: &CommitChanges) -> serde_json::Value {
+        let sha = &commit.sha;
+        let commit_url = format!("{base_url}/repos/owner/repo/commits/{sha}");
+        json!({
+            "url": commit_url,
+            "sha": sha,
+            "node_id": "",
+            "html_url": commit_url,
+            "comments_url": format!("{commit_url}/comments"),
+            "commit": {
+                "url": commit_url,
+                "author": commit.author.as_ref().map(|name| json!({
+                    "name": name,
+                    "email": "",
+                    "date": "2023-01-01T00:00:00Z",
+                })),
+                "committer": null,
+                "message": "Fix all the bugs",
+                "comment_count": 0,
+                "tree": { "sha": sha, "url": commit_url },
+            },
+            "author": null,
+            "committer": null,
+            "parents": [],
+            "files": commit.files.iter().map(|file| json!({
+                "sha": sha,
+                "filename": file.filename,
+                "previous_filename": file.previous_filename,
+                "status": if file.previous_filename.is_some() { "renamed" } else { "modified" },
+                "additions": file.additions,
+                "deletions": file.deletions,
+                "changes": file.lines_changed(),
+                "blob_url": commit_url,
+                "raw_url": commit_url,
+                "contents_url": commit_url,
+            })).collect::<Vec<_>>(),
+        })
+    }
+
+    async fn setup_api(commits: &[CommitChanges]) -> MockServer {
         let owner = "owner";
         let repo = "repo";
         let mock_server = MockServer::start().await;
+        let base_url = mock_server.uri();
+
+        let commits_json: Vec<_> = commits
+            .iter()
+            .map(|commit| repo_commit_json(&base_url, commit))
+            .collect();
+
+        // Serve the commits in two pages to exercise pagination
+        let (first_page, second_page) = commits_json.split_at(commits.len() / 2);
+        let next_page_link =
+            format!("<{base_url}/repos/{owner}/{repo}/commits?page=2>; rel=\"next\"");
+        for (page, link, commits_page) in [
+            (1, Some(next_page_link.as_str()), first_page),
+            (2, None, second_page),
+        ] {
+            let mut response = ResponseTemplate::new(200).set_body_json(commits_page);
+            if let Some(link) = link {
+                response = response.insert_header("link", link);
+            }
+            Mock::given(method("GET"))
+                .and(path(format!("/repos/{owner}/{repo}/commits")))
+                .and(query_param("page", page.to_string()))
+                .respond_with(response)
+                .mount(&mock_server)
+                .await;
+        }
+        for (commit, commit_json) in commits.iter().zip(commits_json) {
+            Mock::given(method("GET"))
+                .and(path(format!(
+                    "/repos/{owner}/{repo}/commits/{}",
+                    commit.sha
+                )))
+                .respond_with(ResponseTemplate::new(200).set_body_json(commit_json))
+                .mount(&mock_server)
+                .await;
+        }
+        setup_error_handler(
+            &mock_server,
+            &format!("GET on /repo/{owner}/{repo}/commits was not received"),
+        )
+        .await;
+        mock_server
+    }
+
+    async fn setup(commits: &[CommitChanges]) -> Result<(MockServer, GitHubHistory)> {
+        let server = setup_api(commits).await;
+        let octocrab = Octocrab::builder().base_uri(server.uri())?.build()?;
+        Ok((server, GitHubHistory::new(octocrab, "owner", "repo")))
+    }
+
+    /// GraphQL history of the commits, in two pages, on top of the REST API of `setup_api`. Every other commit
+    /// merged a pull request, telling its files.
+    async fn setup_graphql(
+        commits: &[CommitChanges],
+    ) -> Result<(MockServer, GitHubGraphQlHistory)> {
+        let (server, rest_history) = setup(commits).await?;
+        let base_url = server.uri();
+        let history_nodes: Vec<_> = commits
+            .iter()
+            .enumerate()
+            .map(|(index, commit)| {
+                let parents: Vec<_> = commits
+                    .get(index + 1)
+                    .map(|parent| json!({ "oid": parent.sha }))
+                    .into_iter()
+                    .collect();
+                let files: Vec<_> = commit
+                    .files
+                    .iter()
+                    .map(|file| {
+                        json!({
+                            "path": file.filename,
+                            "additions": file.additions,
+                            "deletions": file.deletions,
+                            "changeType": "MODIFIED",
+                        })
+                    })
+                    .collect();
+                let pull_requests: Vec<_> = (index % 2 == 0)
+                    .then(|| {
+                        json!({
+                            "mergeCommit": { "oid": commit.sha },
+                            "changedFiles": files.len(),
+                            "additions": 0,
+                            "deletions": 0,
+                            "files": { "nodes": files },
+                        })
+                    })
+                    .into_iter()
+                    .collect();
+                json!({
+                    "oid": commit.sha,
+                    "message": commit.message,
+                    "authoredDate": "2023-01-01T00:00:00Z",
+                    "author": { "name": commit.author },
+                    "changedFilesIfAvailable": commit.files.len(),
+                    "additions": 0,
+                    "deletions": 0,
+                    "parents": { "totalCount": parents.len(), "nodes": parents },
+                    "associatedPullRequests": { "nodes": pull_requests },
+                })
+            })
+            .collect();
+        let (first_page, second_page) = history_nodes.split_at(commits.len() / 2);
+
+        for (cursor, nodes, next_cursor) in [
+            (None, first_page, Some("second-page")),
+            (Some("second-page"), second_page, None),
+        ] {
+            Mock::given(method("POST"))
+                .and(path("/graphql"))
+                .and(body_partial_json(json!({ "variables": { "cursor": cursor } })))
+                .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                    "data": { "repository": { "object": { "history": {
+                        "pageInfo": { "hasNextPage": next_cursor.is_some(), "endCursor": next_cursor },
+                        "nodes": nodes,
+                    }}}}
+                })))
+                .mount(&server)
+                .await;
+        }
+        for (commit, parent) in commits.iter().zip(commits.iter().skip(1)) {
+            Mock::given(method("GET"))
+                .and(path(format!(
+                    "/repos/owner/repo/compare/{}...{}",
+                    parent.sha, commit.sha
+                )))
+                .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                    "files": repo_commit_json(&base_url, commit)["files"],
+                })))
+                .with_priority(1)
+                .mount(&server)
+                .await;
+        }
+        Ok((server, GitHubGraphQlHistory::new(rest_history)))
+    }
+
+    async fn setup_gitlab_api(commits: &[CommitChanges]) -> MockServer {
+        let project_url = "/api/v4/projects/owner%2Frepo";
+        let mock_server = MockServer::start().await;
+
+        // Serve the commits in two pages to exercise pagination
+        let (first_page, second_page) = commits.split_at(commits.len() / 2);
+        for (page, next_page, commits_page) in [(1, "2", first_page), (2, "", second_page)] {
+            let commits_json: Vec<_> = commits_page
+                .iter()
+                .map(|commit| json!({ "id": commit.sha }))
+                .collect();
+            Mock::given(method("GET"))
+                .and(path(format!("{project_url}/repository/commits")))
+                .and(query_param("page", page.to_string()))
+                .and(header("PRIVATE-TOKEN", "token"))
+                .respond_with(
+                    ResponseTemplate::new(200)
+                        .insert_header("x-next-page", next_page)
+                        .set_body_json(commits_json),
+                )
+                .mount(&mock_server)
+                .await;
+        }
+        for commit in commits {
+            let diffs_json: Vec<_> = commit
+                .files
+                .iter()
+                .map(|file| json!({ "old_path": file.filename, "new_path": file.filename }))
+                .collect();
+            Mock::given(method("GET"))
+                .and(path(format!(
+                    "{project_url}/repository/commits/{}/diff",
+                    commit.sha
+                )))
+                .respond_with(ResponseTemplate::new(200).set_body_json(diffs_json))
+                .mount(&mock_server)
+                .await;
+        }
+        setup_error_handler(
+            &mock_server,
+            &format!("GET on {project_url}/repository/commits was not received"),
+        )
+        .await;
+        mock_server
+    }
+
+    async fn setup_bitbucket_api(
+        commits: &[CommitChanges],
+        too_old_commit: &CommitChanges,
+    ) -> MockServer {
+        let repo_url = "/rest/api/1.0/projects/PROJ/repos/repo";
+        let mock_server = MockServer::start().await;
+
+        // Serve the commits in topological order over three pages, the last one out of the analysis window, and stop
+        // there without asking for the fourth one. The first commit was rebased: committed recently but authored
+        // before the window, and a branch committed before the window was merged in the middle of the first page, so
+        // neither is counted nor ends the listing
+        let now = Utc::now().timestamp_millis();
+        let (newer, older) = commits.split_at(commits.len() / 2);
+        let commit_json = |commit: &CommitChanges, timestamp| json!({ "id": commit.sha, "authorTimestamp": timestamp, "committerTimestamp": timestamp });
+        let mut first_page =
+            vec![json!({ "id": "rebased", "authorTimestamp": 0, "committerTimestamp": now })];
+        first_page.extend(newer.iter().map(|commit| commit_json(commit, now)));
+        first_page.push(json!({ "id": "merged", "authorTimestamp": 0, "committerTimestamp": 0 }));
+        let second_page: Vec<_> = older
+            .iter()
+            .map(|commit| commit_json(commit, now))
+            .collect();
+        let third_page = vec![commit_json(too_old_commit, 0)];
+        let pages_start = [0, first_page.len(), first_page.len() + second_page.len()];
+        for (page, commits_page) in [first_page, second_page, third_page]
+            .into_iter()
+            .enumerate()
+        {
+            Mock::given(method("GET"))
+                .and(path(format!("{repo_url}/commits")))
+                .and(query_param("start", pages_start[page].to_string()))
+                .and(header("Authorization", "Bearer token"))
+                .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                    "values": commits_page,
+                    "isLastPage": false,
+                    "nextPageStart": pages_start.get(page + 1).copied().unwrap_or(1000),
+                })))
+                .mount(&mock_server)
+                .await;
+        }
         Mock::given(method("GET"))
-            .and(path(format!("/repos/{owner}/{repo}/commits")))
-            .respond_with(template)
+            .and(path(format!("{repo_url}/commits")))
+            .and(query_param("start", "1000"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "values": [], "isLastPage": true,
+            })))
+            .expect(0)
             .mount(&mock_server)
             .await;
+        let (rebased_commit, merged_commit) = (
+            CommitChanges::changing("rebased", &["LICENSE"]),
+            CommitChanges::changing("merged", &["LICENSE"]),
+        );
+        for commit in commits
+            .iter()
+            .chain([too_old_commit, &rebased_commit, &merged_commit])
+        {
+            let changes_json: Vec<_> = commit
+                .files
+                .iter()
+                .map(|file| json!({ "path": { "toString": file.filename }, "type": "MODIFY" }))
+                .collect();
+            Mock::given(method("GET"))
+                .and(path(format!("{repo_url}/commits/{}/changes", commit.sha)))
+                .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                    "values": changes_json,
+                    "isLastPage": true,
+                })))
+                .mount(&mock_server)
+                .await;
+        }
         setup_error_handler(
             &mock_server,
-            &format!("GET on /repo/{owner}/{repo}/commits was not received"),
+            &format!("GET on {repo_url}/commits was not received"),
         )
         .await;
         mock_server
     }
 
-    async fn setup(response_template: ResponseTemplate) -> Result<Octocrab> {
-        let server = setup_api(response_template).await;
-        let token = std::env::var("GITHUB_TOKEN").expect("GITHUB_TOKEN env variable is required");
-        Ok(Octocrab::builder().personal_token(token).build()?)
+    async fn setup_gitea_api(
+        commits: &[CommitChanges],
+        too_old_commit: &CommitChanges,
+    ) -> MockServer {
+        let repo_url = "/api/v1/repos/owner/repo";
+        let mock_server = MockServer::start().await;
+
+        // Serve the commits in two pages to exercise pagination, the last one out of the analysis window as an older
+        // Gitea ignoring `since` would
+        let now = Utc::now().to_rfc3339();
+        let mut commits_json: Vec<_> = commits
+            .iter()
+            .map(|commit| json!({ "sha": commit.sha, "commit": { "author": { "date": now }, "committer": { "date": now } } }))
+            .collect();
+        commits_json.push(json!({
+            "sha": too_old_commit.sha,
+            "commit": { "author": { "date": "1970-01-01T00:00:00Z" }, "committer": { "date": "1970-01-01T00:00:00Z" } },
+        }));
+        let (first_page, second_page) = commits_json.split_at(commits.len() / 2);
+        for (page, has_more, commits_json) in [(1, "true", first_page), (2, "false", second_page)] {
+            Mock::given(method("GET"))
+                .and(path(format!("{repo_url}/commits")))
+                .and(query_param("page", page.to_string()))
+                .and(header("Authorization", "token token"))
+                .respond_with(
+                    ResponseTemplate::new(200)
+                        .insert_header("x-hasmore", has_more)
+                        .set_body_json(commits_json),
+                )
+                .mount(&mock_server)
+                .await;
+        }
+        for commit in commits.iter().chain([too_old_commit]) {
+            let files_json: Vec<_> = commit
+                .files
+                .iter()
+                .map(|file| json!({ "filename": file.filename, "status": "modified" }))
+                .collect();
+            Mock::given(method("GET"))
+                .and(path(format!("{repo_url}/git/commits/{}", commit.sha)))
+                .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                    "sha": commit.sha,
+                    "files": files_json,
+                })))
+                .mount(&mock_server)
+                .await;
+        }
+        setup_error_handler(
+            &mock_server,
+            &format!("GET on {repo_url}/commits was not received"),
+        )
+        .await;
+        mock_server
     }
 
-    fn load_responses() -> String {
-        fs::read_to_string("data/responses.dat").expect("Could not read test responses file")
+    /// Builds a history where every file is changed by as many commits as its expected count.
+    fn history_from_counts(counts: &ChangedFileCounts) -> Vec<CommitChanges> {
+        let num_commits = counts.iter().map(|(_, count)| *count).max().unwrap_or(0);
+        (0..num_commits)
+            .map(|commit_index| CommitChanges {
+                sha: format!("{commit_index:040x}"),
+                files: counts
+                    .iter()
+                    .filter(|(_, count)| *count > commit_index)
+                    .map(|(filename, _)| FileChange {
+                        filename: filename.clone(),
+                        ..Default::default()
+                    })
+                    .collect(),
+                ..Default::default()
+            })
+            .collect()
+    }
+
+    fn expected_top_5_changed_files() -> ChangedFileCounts {
+        vec![
+            ("README.md".into(), 15),
+            ("generate-quantum-programs.py".into(), 7),
+            ("large_quantum_program_input.json".into(), 4),
+            ("quantum_program_input.json".into(), 3),
+            ("LICENSE".into(), 1),
+        ]
     }
 
     #[tokio::test]
     async fn get_the_top_5_changed_files() {
-        let github_response = load_responses();
+        let expected = expected_top_5_changed_files();
+        let (_server, github_history) = setup(&history_from_counts(&expected)).await.unwrap();
+
+        let top_5_changed_files = github_history
+            .get_top_changed_files(5, &HistoryQuery::default())
+            .await;
+
+        assert_eq!(expected, top_5_changed_files.unwrap());
+    }
+
+    #[tokio::test]
+    async fn record_and_replay_the_github_responses() {
+        let expected = expected_top_5_changed_files();
+        let forge = setup_api(&history_from_counts(&expected)).await;
+        let github_history = |base_url: &str| -> Result<GitHubHistory> {
+            let octocrab = Octocrab::builder().base_uri(base_url)?.build()?;
+            Ok(GitHubHistory::new(octocrab, "owner", "repo"))
+        };
+        // The same window both times, as the replayed requests have to be the recorded ones
+        let query = HistoryQuery::default();
+        let responses = RecordedResponses::new(Utc::now());
+
+        let recording = ForgeFixture::record(&forge.uri(), responses.clone()).unwrap();
+        let recorded = github_history(recording.base_url())
+            .unwrap()
+            .get_top_changed_files(5, &query)
+            .await
+            .unwrap();
+        let num_forge_requests = forge.received_requests().await.unwrap().len();
+        let replaying = ForgeFixture::replay(responses).unwrap();
+        let replayed = github_history(replaying.base_url())
+            .unwrap()
+            .get_top_changed_files(5, &query)
+            .await;
+
+        assert_eq!(expected, recorded);
+        assert_eq!(recorded, replayed.unwrap());
+        // Every request was recorded, so none reached the forge again
+        assert_eq!(
+            num_forge_requests,
+            forge.received_requests().await.unwrap().len()
+        );
+    }
+
+    #[tokio::test]
+    async fn get_the_top_5_changed_files_listing_the_commits_with_graphql() {
+        let expected = expected_top_5_changed_files();
+        let (_server, graphql_history) = setup_graphql(&history_from_counts(&expected))
+            .await
+            .unwrap();
+
+        let top_5_changed_files = graphql_history
+            .get_top_changed_files(5, &HistoryQuery::default())
+            .await;
+
+        assert_eq!(expected, top_5_changed_files.unwrap());
+    }
+
+    /// How many commits were compared with their parent with the REST API.
+    async fn num_comparisons(server: &MockServer) -> usize {
+        server
+            .received_requests()
+            .await
+            .unwrap()
+            .iter()
+            .filter(|request| request.url.path().contains("/compare/"))
+            .count()
+    }
+
+    #[tokio::test]
+    async fn take_the_files_of_the_merged_pull_requests_from_graphql() {
+        let commits = history_from_counts(&expected_top_5_changed_files());
+        let (server, graphql_history) = setup_graphql(&commits).await.unwrap();
+        let changed_lines_query = HistoryQuery {
+            changed_lines: true,
+            ..HistoryQuery::default()
+        };
+
+        let mut num_comparisons_so_far = vec![];
+        for query in [HistoryQuery::default(), changed_lines_query] {
+            let listed_commits: Vec<CommitChanges> = graphql_history
+                .commits(&query)
+                .await
+                .unwrap()
+                .try_collect()
+                .await
+                .unwrap();
+            let files = |commit: &CommitChanges| (commit.sha.clone(), commit.files.clone());
+            assert_eq!(
+                commits.iter().map(files).collect::<Vec<_>>(),
+                listed_commits.iter().map(files).collect::<Vec<_>>()
+            );
+            num_comparisons_so_far.push(num_comparisons(&server).await);
+        }
+
+        // The 15th commit is the first one, with nothing to compare with, and pull requests don't tell changed lines
+        assert_eq!(15, commits.len());
+        assert_eq!(vec![7, 7 + 14], num_comparisons_so_far);
+    }
+
+    #[tokio::test]
+    async fn take_the_commits_fetched_in_previous_runs_from_the_cache() {
+        let expected = expected_top_5_changed_files();
+        let (server, github_history) = setup(&history_from_counts(&expected)).await.unwrap();
+        let cache_directory = tempfile::tempdir().unwrap();
+        let github_history =
+            github_history.with_cache(Some(CommitCache::new(cache_directory.path().into())));
+        github_history
+            .get_top_changed_files(5, &HistoryQuery::default())
+            .await
+            .unwrap();
+        Mock::given(method("GET"))
+            .and(path_regex("^/repos/owner/repo/commits/.+"))
+            .respond_with(
+                ResponseTemplate::new(404).set_body_json(json!({ "message": "Not Found" })),
+            )
+            .with_priority(1)
+            .mount(&server)
+            .await;
+        let query = HistoryQuery::default();
 
-        let response_template = ResponseTemplate::new(200).set_body_json(github_response);
+        let top_5_changed_files = github_history.get_top_changed_files(5, &query).await;
 
-        let octocrab = setup(response_template).await;
+        assert_eq!(expected, top_5_changed_files.unwrap());
+        assert!(query.failed_commits.is_empty());
+    }
+
+    #[tokio::test]
+    async fn retry_the_commits_fetched_while_rate_limited() {
+        let expected = expected_top_5_changed_files();
+        let history = history_from_counts(&expected);
+        let (server, github_history) = setup(&history).await.unwrap();
+        let rate_limit_reset = Utc::now().timestamp().to_string();
+        Mock::given(method("GET"))
+            .and(path(format!(
+                "/repos/owner/repo/commits/{}",
+                history[0].sha
+            )))
+            .respond_with(
+                ResponseTemplate::new(403)
+                    .insert_header("x-ratelimit-remaining", "0")
+                    .insert_header("x-ratelimit-reset", rate_limit_reset.as_str())
+                    .set_body_json(json!({ "message": "API rate limit exceeded" })),
+            )
+            .up_to_n_times(1)
+            .with_priority(1)
+            .mount(&server)
+            .await;
+
+        let top_5_changed_files = github_history
+            .get_top_changed_files(5, &HistoryQuery::default())
+            .await;
+
+        assert_eq!(expected, top_5_changed_files.unwrap());
+    }
+
+    #[tokio::test]
+    async fn retry_the_listing_on_server_errors() {
+        let expected = expected_top_5_changed_files();
+        let (server, github_history) = setup(&history_from_counts(&expected)).await.unwrap();
+        Mock::given(method("GET"))
+            .and(path("/repos/owner/repo/commits"))
+            .and(query_param("page", "2"))
+            .respond_with(
+                ResponseTemplate::new(502).set_body_json(json!({ "message": "Bad Gateway" })),
+            )
+            .up_to_n_times(1)
+            .with_priority(1)
+            .mount(&server)
+            .await;
+        let query = HistoryQuery::default();
+
+        let top_5_changed_files = github_history.get_top_changed_files(5, &query).await;
+
+        assert_eq!(expected, top_5_changed_files.unwrap());
+        assert!(query.failed_commits.is_empty());
+    }
 
-        let top_5_changed_files = octocrab.get_top_changed_files(5, "owner", "repo").await;
+    #[tokio::test]
+    async fn record_the_commits_that_could_not_be_fetched() {
+        let counts = expected_top_5_changed_files();
+        let history = history_from_counts(&counts);
+        let (server, github_history) = setup(&history).await.unwrap();
+        Mock::given(method("GET"))
+            .and(path(format!(
+                "/repos/owner/repo/commits/{}",
+                history[0].sha
+            )))
+            .respond_with(
+                ResponseTemplate::new(404).set_body_json(json!({ "message": "Not Found" })),
+            )
+            .with_priority(1)
+            .mount(&server)
+            .await;
+        let query = HistoryQuery::default();
+
+        let changed_files = count_file_changes(&github_history, &query).await.unwrap();
+
+        // The first commit changes every file
+        let counts_without_the_first_commit: ChangedFileCounts = counts
+            .into_iter()
+            .map(|(filename, count)| (filename, count - 1))
+            .filter(|(_, count)| *count > 0)
+            .collect();
+        let failed_commits = query.failed_commits.to_vec();
+        assert_eq!(
+            counts_without_the_first_commit,
+            changed_files
+                .into_iter()
+                .map(|stats| (stats.filename, stats.num_commits))
+                .collect::<ChangedFileCounts>()
+        );
+        assert_eq!(1, failed_commits.len());
+        assert_eq!(Some(history[0].sha.clone()), failed_commits[0].sha);
+    }
 
+    #[tokio::test]
+    async fn count_the_changes_of_renamed_files_under_their_current_path() {
+        let mut history =
+            history_from_counts(&vec![("README.md".into(), 2), ("LICENSE".into(), 2)]);
+        history.insert(
+            0,
+            CommitChanges {
+                sha: "rename".into(),
+                files: vec![FileChange {
+                    filename: "docs/README.md".into(),
+                    previous_filename: Some("README.md".into()),
+                    ..Default::default()
+                }],
+                ..Default::default()
+            },
+        );
+        let (_server, github_history) = setup(&history).await.unwrap();
+
+        let top_changed_files = github_history
+            .get_top_changed_files(5, &HistoryQuery::default())
+            .await;
+
+        let expected: ChangedFileCounts = vec![("docs/README.md".into(), 3), ("LICENSE".into(), 2)];
+        assert_eq!(expected, top_changed_files.unwrap());
+    }
+
+    #[tokio::test]
+    async fn rank_the_changed_files_by_lines_changed() {
+        let file_change = |filename: &str, additions, deletions| FileChange {
+            filename: filename.into(),
+            additions,
+            deletions,
+            ..Default::default()
+        };
+        let history = vec![
+            CommitChanges {
+                sha: "typo".into(),
+                author: Some("Grace".into()),
+                files: vec![file_change("README.md", 1, 1)],
+                ..Default::default()
+            },
+            CommitChanges {
+                sha: "another-typo".into(),
+                author: Some("Ada".into()),
+                files: vec![file_change("README.md", 1, 1)],
+                ..Default::default()
+            },
+            CommitChanges {
+                sha: "rewrite".into(),
+                author: Some("Ada".into()),
+                files: vec![file_change("src/lib.rs", 300, 200)],
+                ..Default::default()
+            },
+        ];
+        let (_server, github_history) = setup(&history).await.unwrap();
+
+        let top_changed_files = github_history
+            .get_top_changed_file_stats(5, &HistoryQuery::default(), RankBy::LinesChanged)
+            .await
+            .unwrap();
+
+        let commit_date = "2023-01-01T00:00:00Z".parse().unwrap();
         let expected = vec![
-            ("README.md".into(), 15),
+            ChangedFileStats {
+                filename: "src/lib.rs".into(),
+                num_commits: 1,
+                lines_changed: 500,
+                num_lines: None,
+                authors: vec![AuthorChanges {
+                    author: "Ada".into(),
+                    num_commits: 1,
+                    lines_changed: 500,
+                    last_change: Some(commit_date),
+                }],
+                num_bug_fixes: 0,
+            },
+            ChangedFileStats {
+                filename: "README.md".into(),
+                num_commits: 2,
+                lines_changed: 4,
+                num_lines: None,
+                authors: vec![
+                    AuthorChanges {
+                        author: "Grace".into(),
+                        num_commits: 1,
+                        lines_changed: 2,
+                        last_change: Some(commit_date),
+                    },
+                    AuthorChanges {
+                        author: "Ada".into(),
+                        num_commits: 1,
+                        lines_changed: 2,
+                        last_change: Some(commit_date),
+                    },
+                ],
+                num_bug_fixes: 0,
+            },
+        ];
+        assert_eq!(expected, top_changed_files);
+    }
+
+    #[test]
+    fn relative_churn_is_relative_to_the_file_size() {
+        let stats = ChangedFileStats {
+            filename: "src/lib.rs".into(),
+            num_commits: 3,
+            lines_changed: 50,
+            num_lines: Some(200),
+            ..Default::default()
+        };
+
+        assert_eq!(Some(0.25), stats.relative_churn());
+        assert_eq!(
+            None,
+            ChangedFileStats {
+                num_lines: None,
+                ..stats
+            }
+            .relative_churn()
+        );
+    }
+
+    #[tokio::test]
+    async fn get_the_top_5_changed_files_from_gitlab() {
+        let expected = expected_top_5_changed_files();
+        let server = setup_gitlab_api(&history_from_counts(&expected)).await;
+        let gitlab_history = GitLabHistory::new(&server.uri(), "owner/repo", "token");
+
+        let top_5_changed_files = gitlab_history
+            .get_top_changed_files(5, &HistoryQuery::default())
+            .await;
+
+        assert_eq!(expected, top_5_changed_files.unwrap());
+    }
+
+    #[tokio::test]
+    async fn get_the_top_5_changed_files_from_bitbucket() {
+        let expected = expected_top_5_changed_files();
+        let too_old_commit = CommitChanges::changing("old", &["LICENSE"]);
+        let server = setup_bitbucket_api(&history_from_counts(&expected), &too_old_commit).await;
+        let bitbucket_history = BitbucketHistory::new(&server.uri(), "PROJ", "repo", "token");
+
+        let top_5_changed_files = bitbucket_history
+            .get_top_changed_files(5, &HistoryQuery::default())
+            .await;
+
+        assert_eq!(expected, top_5_changed_files.unwrap());
+    }
+
+    #[tokio::test]
+    async fn get_the_top_5_changed_files_from_gitea() {
+        let expected = expected_top_5_changed_files();
+        let too_old_commit = CommitChanges::changing("old", &["LICENSE"]);
+        let server = setup_gitea_api(&history_from_counts(&expected), &too_old_commit).await;
+        let gitea_history = GiteaHistory::new(&server.uri(), "owner", "repo", "token");
+
+        let top_5_changed_files = gitea_history
+            .get_top_changed_files(5, &HistoryQuery::default())
+            .await;
+
+        assert_eq!(expected, top_5_changed_files.unwrap());
+    }
+
+    #[tokio::test]
+    async fn filter_out_paths_before_picking_the_top_changed_files() {
+        let mut history_counts = expected_top_5_changed_files();
+        history_counts.push(("CONTRIBUTING.md".into(), 1));
+        let recorded_history = history_from_counts(&history_counts);
+        let query = HistoryQuery {
+            path_filter: PathFilter::new(&[], &["*.md".into()], Some("*.json\n")).unwrap(),
+            ..HistoryQuery::default()
+        };
+
+        let top_2_changed_files = recorded_history.get_top_changed_files(2, &query).await;
+
+        let expected: ChangedFileCounts = vec![
             ("generate-quantum-programs.py".into(), 7),
-            ("large_quantum_program_input.json".into(), 4),
-            ("quantum_program_input.json".into(), 3),
             ("LICENSE".into(), 1),
         ];
+        assert_eq!(expected, top_2_changed_files.unwrap());
+    }
 
-        assert_eq!(expected, top_5_changed_files.unwrap());
+    #[tokio::test]
+    async fn get_the_top_changed_files_from_a_recorded_history() {
+        let mut history_counts = expected_top_5_changed_files();
+        history_counts.push(("CONTRIBUTING.md".into(), 1));
+        let recorded_history = history_from_counts(&history_counts);
+
+        let top_3_changed_files = recorded_history
+            .get_top_changed_files(3, &HistoryQuery::default())
+            .await;
+
+        assert_eq!(
+            expected_top_5_changed_files()[..3].to_vec(),
+            top_3_changed_files.unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn leave_merges_and_bots_out_but_follow_their_renames() {
+        let commit = |author: &str, num_parents, files: Vec<FileChange>| CommitChanges {
+            sha: format!("{author}-{}", files.len()),
+            author: Some(author.into()),
+            num_parents: Some(num_parents),
+            files,
+            ..Default::default()
+        };
+        let file_change = |filename: &str, previous_filename: Option<&str>| FileChange {
+            filename: filename.into(),
+            previous_filename: previous_filename.map(String::from),
+            ..Default::default()
+        };
+        let history = vec![
+            commit("Ada", 1, vec![file_change("src/main.rs", None)]),
+            commit("dependabot[bot]", 1, vec![file_change("Cargo.lock", None)]),
+            commit("Ada", 2, vec![file_change("src/main.rs", Some("main.rs"))]),
+            commit("Grace", 1, vec![file_change("main.rs", None)]),
+        ];
+        let query = HistoryQuery {
+            commit_filter: CommitFilter::new(true, &["*[bot]".into()], None, None).unwrap(),
+            ..HistoryQuery::default()
+        };
+
+        let top_changed_files = history.get_top_changed_files(5, &query).await;
+
+        let expected: ChangedFileCounts = vec![("src/main.rs".into(), 2)];
+        assert_eq!(expected, top_changed_files.unwrap());
+    }
+
+    #[test]
+    fn select_the_top_k_keeping_the_order_of_ties() {
+        let counts = [("a", 1), ("b", 3), ("c", 2), ("d", 3), ("e", 2), ("f", 5)];
+        let by_count = |(_, count1): &(&str, u32), (_, count2): &(&str, u32)| count1.cmp(count2);
+
+        assert_eq!(
+            vec![("f", 5), ("b", 3), ("d", 3), ("c", 2)],
+            top_k_by(counts, 4, by_count)
+        );
+        assert_eq!(6, top_k_by(counts, 10, by_count).len());
+        assert!(top_k_by(counts, 0, by_count).is_empty());
     }
 }
@@ -1,21 +1,57 @@
 mod complexity;
+mod function_churn;
+mod hotspot;
 mod report;
+mod trend;
 
-use anyhow::Result;
-use clap::Parser;
-use complexity::{compute_cognitive_index, FunctionComplexity, ProgrammingLang};
-use complexity_radar::TopChangedFilesExt;
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
+use clap::{ArgGroup, Parser, ValueEnum};
+use complexity::{
+    compute_cognitive_index, compute_cognitive_index_of_code, FunctionComplexity, ProgrammingLang,
+};
+use complexity_radar::coupling::{ChangeCouplingExt, CouplingDegree, CouplingOptions};
+use complexity_radar::defects::{
+    correlation, get_top_defect_prone_files, BugFixClassifier, DEFAULT_BUG_FIX_PATTERNS,
+};
+use complexity_radar::history::{
+    BitbucketHistory, ChangeHistoryProvider, CommitCache, CommitFilter, ForgeFixture,
+    GitHubGraphQlHistory, GitHubHistory, GitLabHistory, GiteaHistory, HistoryQuery, HistoryStore,
+    MiningProgress, PathFilter, RecordedResponses, WindowBound, WithForgeOptions,
+    DEFAULT_CONCURRENCY, GITHUB_DEFAULT_BASE_URL, GITLAB_DEFAULT_BASE_URL, IGNORE_FILENAME,
+};
+use complexity_radar::knowledge::{knowledge_of, knowledge_of_directories, last_activity};
+use complexity_radar::{
+    count_file_changes, top_k_by, ChangedFileStats, RankBy, TopChangedFilesExt,
+};
+use function_churn::{count_function_changes, rank_function_churn};
+use hotspot::{find_hotspots, ComplexityAggregate};
 use octocrab::Octocrab;
-use report::{print_heat_map_report, print_top_complexities_report};
+use report::{
+    print_coupling_report, print_defects_report, print_failed_commits_summary,
+    print_function_churn_report, print_heat_map_report, print_hotspots_report,
+    print_knowledge_report, print_top_complexities_report, print_trend_report,
+    show_mining_progress,
+};
+use std::path::{Path, PathBuf};
+use trend::{compute_complexity_trend, TrendFormat};
 
 #[derive(Parser, Debug)]
 #[clap(name = "complexity-radar")]
+// Only one kind of report at a time
+#[clap(group(ArgGroup::new("report").multiple(false)))]
 #[clap(author = env!("CARGO_PKG_AUTHORS"), version = env!("CARGO_PKG_VERSION"), about = env!("CARGO_PKG_DESCRIPTION"))]
 pub struct CommandLineArguments {
-    /// set base url, like: https://your-company.github.com/
+    /// Forge hosting the repository
+    #[clap(short = 'f', long = "forge", value_enum, default_value_t = Forge::Github)]
+    pub forge: Forge,
+
+    /// set base url, like: https://your-company.github.com/ or https://gitlab.your-company.com/
+    /// Required for Bitbucket Server and Gitea
     #[clap(short = 'b', long = "base-url")]
     pub base_url: Option<String>,
 
+    /// User or group owning the repository (the project key on Bitbucket)
     #[clap(short = 'u', long = "github-user")]
     pub github_user: String,
 
@@ -26,63 +62,613 @@ pub struct CommandLineArguments {
     #[clap(short = 'n', long = "num-rows", default_value_t = 5)]
     pub num_rows: usize,
 
-    /// GitHub token to authenticate against GitHub API
+    /// Token to authenticate against the forge API. Defaults to the GITHUB_TOKEN, GITLAB_TOKEN, BITBUCKET_TOKEN
+    /// or GITEA_TOKEN env variable, depending on the forge
     #[clap(short = 't', long = "token")]
     pub token: Option<String>,
 
-    /// Do not compute complexity, only shows the top modified files of the repo
+    /// Start of the analysis window: a date (2023-01-31), a duration relative to now (90d, 12w, 6m, 1y)
+    /// or a git ref/tag (v1.2.0). Defaults to one year ago
+    #[clap(long)]
+    pub since: Option<WindowBound>,
+
+    /// End of the analysis window, in the same formats as --since. Defaults to now
+    #[clap(long)]
+    pub until: Option<WindowBound>,
+
+    /// Branch, tag or SHA to mine and analyze. Defaults to the default branch for the history, and to the local
+    /// files for the complexity
+    #[clap(long = "ref")]
+    pub git_ref: Option<String>,
+
+    /// Only count changes to files matching these globs, like 'src/**/*.rs'. Can be repeated
+    #[clap(long = "include")]
+    pub include: Vec<String>,
+
+    /// Don't count changes to files matching these patterns (gitignore syntax), like 'vendor/' or '*.lock'.
+    /// Can be repeated. Patterns in the .complexityradarignore file of the repository are excluded too
+    #[clap(long = "exclude")]
+    pub exclude: Vec<String>,
+
+    /// Don't count merge commits, the changes they bring are counted in the merged commits already
+    #[clap(long)]
+    pub no_merges: bool,
+
+    /// Don't count commits by authors matching these names, where * matches anything, like '*[bot]'. Can be
+    /// repeated
+    #[clap(long, value_name = "PATTERN")]
+    pub exclude_author: Vec<String>,
+
+    /// Don't count commits whose message matches this regex, like '^(chore|style):'
+    #[clap(long, value_name = "REGEX")]
+    pub exclude_message: Option<String>,
+
+    /// Don't count commits changing more files than this, like mass reformatting or license header updates
+    #[clap(long, value_name = "NUM_FILES")]
+    pub max_files_per_commit: Option<usize>,
+
+    /// How many commits to fetch from the forge at once. Higher values are faster, but may hit the rate limits of
+    /// the forge
+    #[clap(long, default_value_t = DEFAULT_CONCURRENCY)]
+    pub concurrency: usize,
+
+    /// List the commits of GitHub repositories with the GraphQL API, a hundred at a time, instead of the REST API.
+    /// Commits squashing a pull request get their changed files from it, the others still take one REST request each
+    #[clap(long)]
+    pub graphql: bool,
+
+    /// Fetch every commit from the forge, instead of taking the ones fetched in previous runs from the cache
+    #[clap(long)]
+    pub no_cache: bool,
+
+    /// Remove the commits of the repository fetched in previous runs from the cache before the analysis
+    #[clap(long)]
+    pub clear_cache: bool,
+
+    /// Keep the mined history in this SQLite database, and report from it without the forge. Past file contents,
+    /// needed by --trend and --function-churn, still come from the forge with --ingest
+    #[clap(long, value_name = "PATH")]
+    pub store: Option<PathBuf>,
+
+    /// Fetch the commits of the analysis window missing from the --store from the forge before reporting
+    #[clap(long, requires = "store")]
+    pub ingest: bool,
+
+    /// Save every response of the forge used by the analysis in this directory, to replay them with --replay
+    #[clap(long, value_name = "DIR", conflicts_with = "replay")]
+    pub record: Option<PathBuf>,
+
+    /// Replay the forge responses recorded with --record in this directory, without a token or the network
+    #[clap(long, value_name = "DIR")]
+    pub replay: Option<PathBuf>,
+
+    /// Don't show how far mining the history got
     #[clap(long)]
+    pub no_progress: bool,
+
+    /// How to rank the most changed files
+    #[clap(long, value_enum, default_value_t = RankBy::Commits)]
+    pub rank_by: RankBy,
+
+    /// Rank the files by hotspot score, combining how often they change and how complex they are, instead of by
+    /// changes only. The complexity of every changed file is computed to find them
+    #[clap(long, group = "report")]
+    pub hotspots: bool,
+
+    /// How many of the most changed files to score for the --hotspots report. Scoring a file computes its
+    /// complexity, which takes too long for every file changed in a large repository
+    #[clap(long, default_value_t = 100)]
+    pub hotspot_candidates: usize,
+
+    /// How to compute the complexity of a file from the complexity of its functions, for the hotspot score
+    #[clap(long, value_enum, default_value_t = ComplexityAggregate::Sum)]
+    pub complexity_aggregate: ComplexityAggregate,
+
+    /// Show the functions of the top files that change the most, with their complexity, instead of the files
+    #[clap(long, group = "report")]
+    pub function_churn: bool,
+
+    /// Show how the complexity of the top files evolved, computing it at this many points evenly spread over the
+    /// analysis window
+    #[clap(long, value_name = "NUM_POINTS", group = "report")]
+    pub trend: Option<usize>,
+
+    /// Format of the --trend output
+    #[clap(long, value_enum, default_value_t = TrendFormat::Table)]
+    pub trend_format: TrendFormat,
+
+    /// Show the pairs of files that change together the most, instead of the most changed files
+    #[clap(long, group = "report")]
+    pub coupling: bool,
+
+    /// How to compute the coupling degree of two files
+    #[clap(long, value_enum, default_value_t = CouplingDegree::Average)]
+    pub coupling_degree: CouplingDegree,
+
+    /// Leave out of the coupling the files changed by fewer commits
+    #[clap(long, default_value_t = CouplingOptions::default().min_revisions)]
+    pub min_revisions: u32,
+
+    /// Leave out of the coupling the pairs of files changed together by fewer commits
+    #[clap(long, default_value_t = CouplingOptions::default().min_shared_commits)]
+    pub min_shared_commits: u32,
+
+    /// Show how many authors account for most of the changes of the top files and of every directory, and whose
+    /// main developer left
+    #[clap(long, group = "report")]
+    pub knowledge: bool,
+
+    /// Main developers without commits since then, in the same formats as --since, are considered gone
+    #[clap(long, default_value = "6m")]
+    pub inactive_since: WindowBound,
+
+    /// Show the files changed by most bug fixes, with their defect density and complexity
+    #[clap(long, group = "report")]
+    pub defects: bool,
+
+    /// Commits whose message matches this regex fix bugs, for --defects. Can be repeated. Defaults to messages
+    /// mentioning fixes, bugs, defects or hotfixes
+    #[clap(long = "bug-pattern")]
+    pub bug_patterns: Vec<String>,
+
+    /// Commits referencing (#123) an issue or pull request with this label fix bugs too, for --defects.
+    /// Only supported on GitHub
+    #[clap(long)]
+    pub bug_label: Option<String>,
+
+    /// Fail if any commit can't be fetched, instead of reporting without it
+    #[clap(long)]
+    pub strict: bool,
+
+    /// Do not compute complexity, only shows the top modified files of the repo
+    #[clap(long, group = "report")]
     pub heat_map_only: bool,
 }
 
+#[derive(ValueEnum, Clone, Copy, Debug)]
+pub enum Forge {
+    Github,
+    Gitlab,
+    /// Bitbucket Server / Data Center
+    Bitbucket,
+    /// Gitea or Forgejo
+    #[value(alias = "forgejo")]
+    Gitea,
+}
+
+impl Forge {
+    fn token_env_variable(&self) -> &'static str {
+        match self {
+            Forge::Github => "GITHUB_TOKEN",
+            Forge::Gitlab => "GITLAB_TOKEN",
+            Forge::Bitbucket => "BITBUCKET_TOKEN",
+            Forge::Gitea => "GITEA_TOKEN",
+        }
+    }
+}
+
 pub struct TopComplexities {
-    code_filename: String, /* TODO: Use PathBuf? */
-    num_changes: u32,
+    changes: ChangedFileStats,
     function_complexities: Vec<FunctionComplexity>,
 }
 
-#[tokio::main]
-async fn main() -> Result<()> {
-    env_logger::init();
-    let args = CommandLineArguments::parse();
+fn required_base_url(args: &CommandLineArguments) -> Result<&str> {
+    args.base_url
+        .as_deref()
+        .ok_or_else(|| anyhow!("--base-url is required for {:?} repositories", args.forge))
+}
 
-    let token = args.token.map_or(
-        std::env::var("GITHUB_TOKEN").expect("GITHUB_TOKEN env variable is required"),
-        |token| token,
-    );
-
-    let octocrab = match args.base_url {
-        Some(base_url) => Octocrab::builder()
-            .base_uri(base_url)?
-            .personal_token(token)
-            .build()?,
-        _ => Octocrab::builder().personal_token(token).build()?,
+/// Cache of the commits of the analyzed repository, in a directory of its own for every forge instance.
+fn commit_cache(args: &CommandLineArguments) -> Option<CommitCache> {
+    let instance = match &args.base_url {
+        Some(base_url) => base_url
+            .trim_start_matches("https://")
+            .trim_start_matches("http://")
+            .trim_end_matches('/')
+            .replace(['/', ':'], "_"),
+        None => "default".to_string(),
+    };
+    CommitCache::default_root().map(|root| {
+        CommitCache::new(
+            root.join(format!("{:?}", args.forge).to_lowercase())
+                .join(instance)
+                .join(&args.github_user)
+                .join(&args.github_repo),
+        )
+    })
+}
+
+/// Where the API of the forge is, the public instance of the forge by default.
+fn forge_base_url(args: &CommandLineArguments) -> Result<&str> {
+    match (&args.forge, &args.base_url) {
+        (_, Some(base_url)) => Ok(base_url),
+        (Forge::Github, None) => Ok(GITHUB_DEFAULT_BASE_URL),
+        (Forge::Gitlab, None) => Ok(GITLAB_DEFAULT_BASE_URL),
+        _ => required_base_url(args),
+    }
+}
+
+/// The forge provider, sending its requests to the `fixture` recording or replaying them if any.
+fn create_history_provider(
+    args: &CommandLineArguments,
+    cache: Option<CommitCache>,
+    fixture: Option<&ForgeFixture>,
+) -> Result<Box<dyn ChangeHistoryProvider>> {
+    let token_env_variable = args.forge.token_env_variable();
+    // Replayed responses don't need a token
+    let token = match (&args.token, &args.replay) {
+        (Some(token), _) => token.clone(),
+        (None, Some(_)) => String::new(),
+        (None, None) => std::env::var(token_env_variable)
+            .unwrap_or_else(|_| panic!("{token_env_variable} env variable is required")),
+    };
+    let base_url = match fixture {
+        Some(fixture) => fixture.base_url(),
+        None => forge_base_url(args)?,
     };
 
-    let top_changed_files = octocrab
-        .get_top_changed_files(args.num_rows, &args.github_user, &args.github_repo)
+    if args.graphql && !matches!(args.forge, Forge::Github) {
+        return Err(anyhow!(
+            "--graphql is only supported for GitHub repositories"
+        ));
+    }
+
+    Ok(match args.forge {
+        Forge::Github => {
+            let octocrab = Octocrab::builder()
+                .base_uri(base_url)?
+                .personal_token(token)
+                .build()?;
+            let history = GitHubHistory::new(octocrab, &args.github_user, &args.github_repo)
+                .with_concurrency(args.concurrency)
+                .with_cache(cache);
+            match args.graphql {
+                true => Box::new(GitHubGraphQlHistory::new(history)),
+                false => Box::new(history),
+            }
+        }
+        Forge::Gitlab => Box::new(
+            GitLabHistory::new(
+                base_url,
+                &format!("{}/{}", args.github_user, args.github_repo),
+                &token,
+            )
+            .with_concurrency(args.concurrency)
+            .with_cache(cache),
+        ),
+        Forge::Bitbucket => Box::new(
+            BitbucketHistory::new(base_url, &args.github_user, &args.github_repo, &token)
+                .with_concurrency(args.concurrency)
+                .with_cache(cache),
+        ),
+        Forge::Gitea => Box::new(
+            GiteaHistory::new(base_url, &args.github_user, &args.github_repo, &token)
+                .with_concurrency(args.concurrency)
+                .with_cache(cache),
+        ),
+    })
+}
+
+/// Opens the history store, first ingesting the new commits from the forge with --ingest.
+async fn open_history_store(
+    args: &CommandLineArguments,
+    path: &Path,
+    cache: Option<CommitCache>,
+    fixture: Option<&ForgeFixture>,
+    recorded_at: Option<DateTime<Utc>>,
+) -> Result<HistoryStore> {
+    let store = HistoryStore::open(path)?;
+    if !args.ingest {
+        return Ok(store);
+    }
+
+    let forge = create_history_provider(args, cache, fixture)?;
+    let query = create_history_query(args, forge.as_ref(), recorded_at).await?;
+    let num_commits = store.ingest(forge.as_ref(), &query).await?;
+    check_failed_commits(args, &query)?;
+    print_failed_commits_summary(&query.failed_commits.to_vec());
+    eprintln!("Ingested {num_commits} commits into {}", path.display());
+    Ok(store.with_contents_from(forge))
+}
+
+/// The query of the analysis. Recorded analyses run as of the time of the `recorded_at` recording, so they send
+/// the forge the same requests when replayed.
+async fn create_history_query(
+    args: &CommandLineArguments,
+    history: &dyn ChangeHistoryProvider,
+    recorded_at: Option<DateTime<Utc>>,
+) -> Result<HistoryQuery> {
+    let now = recorded_at.unwrap_or_else(Utc::now);
+    let since = match &args.since {
+        Some(since) => since.resolve(history, now).await?,
+        None => HistoryQuery::default_since(now),
+    };
+    let until = match &args.until {
+        Some(until) => Some(until.resolve(history, now).await?),
+        None => recorded_at,
+    };
+    // The ignore file is optional, so any error fetching it just means there's none
+    let ignore_file = history
+        .file_contents(IGNORE_FILENAME, args.git_ref.as_deref())
+        .await
+        .ok();
+    let path_filter = PathFilter::new(&args.include, &args.exclude, ignore_file.as_deref())?;
+    let commit_filter = CommitFilter::new(
+        args.no_merges,
+        &args.exclude_author,
+        args.exclude_message.as_deref(),
+        args.max_files_per_commit,
+    )?;
+
+    Ok(HistoryQuery {
+        git_ref: args.git_ref.clone(),
+        path_filter,
+        commit_filter,
+        progress: match args.no_progress {
+            true => MiningProgress::default(),
+            false => show_mining_progress(),
+        },
+        ..HistoryQuery::new(since, until)
+    })
+}
+
+/// Sum of the complexities of the functions of the file, `None` if it can't be computed.
+async fn compute_total_complexity(
+    history: &dyn ChangeHistoryProvider,
+    code_filename: &str,
+    git_ref: Option<&str>,
+) -> Option<u32> {
+    compute_file_complexities(history, code_filename, git_ref)
+        .await
+        .ok()
+        .map(|function_complexities| {
+            function_complexities
+                .iter()
+                .map(|function_complexity| function_complexity.cognitive_complexity_value as u32)
+                .sum()
+        })
+}
+
+async fn compute_file_complexities(
+    history: &dyn ChangeHistoryProvider,
+    code_filename: &str,
+    git_ref: Option<&str>,
+) -> Result<Vec<FunctionComplexity>> {
+    match git_ref {
+        // Analyze the files as they are at the same ref we mined the history from
+        Some(git_ref) => {
+            let code = history.file_contents(code_filename, Some(git_ref)).await?;
+            compute_cognitive_index_of_code(ProgrammingLang::Rust, code_filename.as_ref(), &code)
+        }
+        None => compute_cognitive_index(ProgrammingLang::Rust, code_filename.into()),
+    }
+}
+
+/// In --strict mode, fails the run if any commit couldn't be fetched, instead of reporting without it.
+fn check_failed_commits(args: &CommandLineArguments, query: &HistoryQuery) -> Result<()> {
+    let failed_commits = query.failed_commits.to_vec();
+    match (args.strict, failed_commits.first()) {
+        (true, Some(failed_commit)) => Err(anyhow!(
+            "{} commits could not be fetched, the first one failed with: {}",
+            failed_commits.len(),
+            failed_commit.error
+        )),
+        _ => Ok(()),
+    }
+}
+
+async fn print_report(
+    args: &CommandLineArguments,
+    history: &dyn ChangeHistoryProvider,
+    store: Option<&HistoryStore>,
+    query: &HistoryQuery,
+) -> Result<()> {
+    if args.coupling {
+        let options = CouplingOptions {
+            degree: args.coupling_degree,
+            min_revisions: args.min_revisions,
+            min_shared_commits: args.min_shared_commits,
+        };
+        let coupled_files = history
+            .get_top_coupled_files(args.num_rows, query, &options)
+            .await?;
+        check_failed_commits(args, query)?;
+        print_coupling_report(&coupled_files);
+        return Ok(());
+    }
+
+    if args.knowledge {
+        let inactive_since = args
+            .inactive_since
+            .resolve(history, query.until.unwrap_or_else(Utc::now))
+            .await?;
+        let changed_files = count_file_changes(history, query).await?;
+        check_failed_commits(args, query)?;
+        let last_activity = last_activity(&changed_files);
+
+        let mut hot_files = Vec::new();
+        for changes in top_k_by(&changed_files, args.num_rows, |changes1, changes2| {
+            changes1.num_commits.cmp(&changes2.num_commits)
+        }) {
+            let knowledge = knowledge_of(
+                &changes.filename,
+                &changes.authors,
+                &last_activity,
+                inactive_since,
+            );
+            let complexity =
+                compute_total_complexity(history, &changes.filename, args.git_ref.as_deref()).await;
+            hot_files.push((knowledge, complexity));
+        }
+        let directories = knowledge_of_directories(
+            &changed_files,
+            &last_activity,
+            inactive_since,
+            args.num_rows,
+        );
+
+        print_knowledge_report(&hot_files, &directories);
+        return Ok(());
+    }
+
+    if args.defects {
+        let bug_patterns = match args.bug_patterns.is_empty() {
+            true => DEFAULT_BUG_FIX_PATTERNS
+                .iter()
+                .map(|pattern| pattern.to_string())
+                .collect(),
+            false => args.bug_patterns.clone(),
+        };
+        let classifier = BugFixClassifier::new(&bug_patterns, args.bug_label.as_deref())?;
+        let top_defect_prone_files =
+            get_top_defect_prone_files(history, args.num_rows, query, &classifier).await?;
+        check_failed_commits(args, query)?;
+        let mut defect_prone_files = Vec::new();
+        for changes in top_defect_prone_files {
+            let complexity =
+                compute_total_complexity(history, &changes.filename, args.git_ref.as_deref()).await;
+            defect_prone_files.push((changes, complexity));
+        }
+        let complexity_and_density: Vec<_> = defect_prone_files
+            .iter()
+            .filter_map(|(changes, complexity)| {
+                Some((f64::from((*complexity)?), changes.defect_density()?))
+            })
+            .collect();
+
+        print_defects_report(&defect_prone_files, correlation(&complexity_and_density));
+        return Ok(());
+    }
+
+    // A file changed less than the top ones can still be a hotspot if it's complex enough
+    let num_candidates = match args.hotspots {
+        true => args.hotspot_candidates.max(args.num_rows),
+        false => args.num_rows,
+    };
+    let top_changed_files = history
+        .get_top_changed_file_stats(num_candidates, query, args.rank_by)
         .await?;
+    check_failed_commits(args, query)?;
 
     if args.heat_map_only {
         print_heat_map_report(&top_changed_files);
         return Ok(());
     }
 
-    let top_complexities = top_changed_files
-        .iter()
-        .map(|(code_filename, num_changes)| {
-            compute_cognitive_index(ProgrammingLang::Rust, code_filename.into())
-                .and_then(|cognitive_complex_indexes| {
-                    Ok(TopComplexities {
-                        code_filename: code_filename.clone(),
-                        num_changes: *num_changes,
-                        function_complexities: cognitive_complex_indexes,
-                    })
-                })
-                .map_err(|msg| msg.into())
-        })
-        .collect::<Vec<Result<TopComplexities, _>>>();
+    if let Some(num_points) = args.trend {
+        let filenames: Vec<_> = top_changed_files
+            .into_iter()
+            .map(|changes| changes.filename)
+            .collect();
+        let trends =
+            compute_complexity_trend(history, store, query, &filenames, num_points).await?;
+        check_failed_commits(args, query)?;
+        print_trend_report(&trends, args.trend_format)?;
+        return Ok(());
+    }
+
+    if args.hotspots {
+        let hotspots = find_hotspots(
+            history,
+            top_changed_files,
+            args.git_ref.as_deref(),
+            args.rank_by,
+            args.complexity_aggregate,
+            args.num_rows,
+        )
+        .await;
+        print_hotspots_report(&hotspots);
+        return Ok(());
+    }
+
+    let mut top_complexities = Vec::new();
+    for changes in top_changed_files {
+        let function_complexities =
+            compute_file_complexities(history, &changes.filename, args.git_ref.as_deref()).await;
+        top_complexities.push(function_complexities.map(|cognitive_complex_indexes| {
+            TopComplexities {
+                changes,
+                function_complexities: cognitive_complex_indexes,
+            }
+        }));
+    }
+
+    if args.function_churn {
+        let top_complexities: Vec<_> = top_complexities.into_iter().flatten().collect();
+        let filenames: Vec<_> = top_complexities
+            .iter()
+            .map(|top_complexities| top_complexities.changes.filename.clone())
+            .collect();
+        let function_changes = count_function_changes(history, query, &filenames).await?;
+        check_failed_commits(args, query)?;
+        print_function_churn_report(&rank_function_churn(&function_changes, &top_complexities));
+        return Ok(());
+    }
 
     print_top_complexities_report(&top_complexities);
     Ok(())
 }
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    env_logger::init();
+    let args = CommandLineArguments::parse();
+
+    let commit_cache = commit_cache(&args);
+    if let (true, Some(commit_cache)) = (args.clear_cache, &commit_cache) {
+        commit_cache.clear().await?;
+    }
+    let recorded = match (&args.record, &args.replay) {
+        (Some(_), _) => Some(RecordedResponses::new(Utc::now())),
+        (None, Some(directory)) => Some(RecordedResponses::open(directory)?),
+        (None, None) => None,
+    };
+    let recorded_at = recorded.as_ref().and_then(RecordedResponses::recorded_at);
+    let fixture = match (&recorded, &args.replay) {
+        (Some(recorded), None) => Some(ForgeFixture::record(
+            forge_base_url(&args)?,
+            recorded.clone(),
+        )?),
+        (Some(recorded), Some(_)) => Some(ForgeFixture::replay(recorded.clone())?),
+        (None, _) => None,
+    };
+    // The commits taken from the cache wouldn't be requested, so they'd miss from the recording
+    let commit_cache = commit_cache.filter(|_| !args.no_cache && fixture.is_none());
+
+    // Reports come from the store if there's one, and straight from the forge otherwise
+    let store = match &args.store {
+        Some(path) => Some(
+            open_history_store(
+                &args,
+                path,
+                commit_cache.clone(),
+                fixture.as_ref(),
+                recorded_at,
+            )
+            .await?,
+        ),
+        None => None,
+    };
+    let forge = match &store {
+        Some(_) => None,
+        None => Some(create_history_provider(
+            &args,
+            commit_cache,
+            fixture.as_ref(),
+        )?),
+    };
+    let history: &dyn ChangeHistoryProvider = match (&store, &forge) {
+        (Some(store), _) => store,
+        (None, forge) => forge
+            .as_deref()
+            .expect("There's a forge when there's no store"),
+    };
+    let query = create_history_query(&args, history, recorded_at).await?;
+    print_report(&args, history, store.as_ref(), &query).await?;
+    print_failed_commits_summary(&query.failed_commits.to_vec());
+    if let (Some(directory), Some(recorded)) = (&args.record, &recorded) {
+        recorded.save(directory)?;
+        eprintln!("Recorded the forge responses in {}", directory.display());
+    }
+    Ok(())
+}
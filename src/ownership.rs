@@ -0,0 +1,94 @@
+use chrono::{DateTime, Utc};
+use itertools::Itertools;
+
+/// Changes made to a file by one of its authors.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct AuthorChanges {
+    pub author: String,
+    pub num_commits: u32,
+    pub lines_changed: u32,
+    /// Date of the most recent of these changes, if the backend tells it
+    pub last_change: Option<DateTime<Utc>>,
+}
+
+/// Who owns a file, from the commits changing it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FileOwnership {
+    pub num_authors: usize,
+    /// Author of most of the commits
+    pub main_developer: String,
+    /// Share of the commits made by the main developer
+    pub commit_share: f64,
+    /// Share of the lines changed by the main developer, `None` if the backend doesn't report lines
+    pub line_share: Option<f64>,
+    /// 0 when a single author makes all the commits, closer to 1 the more authors share them evenly
+    pub fragmentation: f64,
+}
+
+impl FileOwnership {
+    /// Ownership of a file from the changes of each of its authors, `None` if no author is known.
+    pub fn of(authors: &[AuthorChanges]) -> Option<FileOwnership> {
+        let total_commits: u32 = authors.iter().map(|author| author.num_commits).sum();
+        let total_lines: u32 = authors.iter().map(|author| author.lines_changed).sum();
+        // Sorting first keeps ties deterministic
+        let main_developer = authors
+            .iter()
+            .sorted_by(|author1, author2| author1.author.cmp(&author2.author))
+            .max_by_key(|author| (author.num_commits, author.lines_changed))?;
+        let fragmentation = 1.0
+            - authors
+                .iter()
+                .map(|author| (author.num_commits as f64 / total_commits as f64).powi(2))
+                .sum::<f64>();
+
+        Some(FileOwnership {
+            num_authors: authors.len(),
+            main_developer: main_developer.author.clone(),
+            commit_share: main_developer.num_commits as f64 / total_commits as f64,
+            line_share: (total_lines > 0)
+                .then(|| main_developer.lines_changed as f64 / total_lines as f64),
+            fragmentation,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn author_changes(author: &str, num_commits: u32, lines_changed: u32) -> AuthorChanges {
+        AuthorChanges {
+            author: author.into(),
+            num_commits,
+            lines_changed,
+            last_change: None,
+        }
+    }
+
+    #[test]
+    fn find_the_main_developer_and_how_fragmented_the_ownership_is() {
+        let authors = vec![
+            author_changes("Grace", 2, 10),
+            author_changes("Ada", 6, 30),
+            author_changes("Linus", 2, 60),
+        ];
+
+        let ownership = FileOwnership::of(&authors).unwrap();
+
+        assert_eq!(3, ownership.num_authors);
+        assert_eq!("Ada", ownership.main_developer);
+        assert_eq!(0.6, ownership.commit_share);
+        assert_eq!(Some(0.3), ownership.line_share);
+        assert!((ownership.fragmentation - 0.56).abs() < 1e-9);
+    }
+
+    #[test]
+    fn a_single_author_owns_the_whole_file() {
+        let ownership = FileOwnership::of(&[author_changes("Ada", 3, 0)]).unwrap();
+
+        assert_eq!(1.0, ownership.commit_share);
+        assert_eq!(None, ownership.line_share);
+        assert_eq!(0.0, ownership.fragmentation);
+        assert_eq!(None, FileOwnership::of(&[]));
+    }
+}
@@ -1,18 +1,60 @@
+use crate::function_churn::FunctionChurn;
+use crate::hotspot::Hotspot;
+use crate::trend::{FileTrend, TrendFormat};
 use crate::TopComplexities;
 use anyhow::Result;
-use complexity_radar::ChangedFileCounts;
+use complexity_radar::coupling::CoupledFiles;
+use complexity_radar::history::{FailedCommit, MiningProgress, Progress};
+use complexity_radar::knowledge::{KnowledgeDistribution, KEY_AUTHORS_SHARE};
+use complexity_radar::ChangedFileStats;
+use indicatif::{HumanDuration, ProgressBar, ProgressStyle};
+use std::io::IsTerminal;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
 
-pub fn print_report_without_header(top_changed_files: &ChangedFileCounts) {
-    top_changed_files.iter().for_each(|(file, num_changes)| {
-        println!("{}\t{}", file, num_changes);
+fn format_changes(changes: &ChangedFileStats) -> String {
+    let relative_churn = changes
+        .relative_churn()
+        .map_or("-".to_string(), |relative_churn| {
+            format!("{:.2}", relative_churn)
+        });
+    let ownership = changes
+        .ownership()
+        .map_or("-\t-\t-".to_string(), |ownership| {
+            let line_share = ownership.line_share.map_or(String::new(), |line_share| {
+                format!(", {:.0}% of lines", line_share * 100.0)
+            });
+            format!(
+                "{}\t{} ({:.0}%{})\t{:.2}",
+                ownership.num_authors,
+                ownership.main_developer,
+                ownership.commit_share * 100.0,
+                line_share,
+                ownership.fragmentation
+            )
+        });
+    format!(
+        "{}\t{}\t{}\t{}\t{}",
+        changes.filename, changes.num_commits, changes.lines_changed, relative_churn, ownership
+    )
+}
+
+const CHANGES_HEADER: &str = "File\t\tNumber of changes\tLines changed\tRelative churn\tAuthors\tMain developer\tFragmentation";
+
+fn print_header() {
+    println!("{}", format!("{:80}", "-").replace(" ", "-"));
+    println!("{CHANGES_HEADER}");
+    println!("{}", format!("{:80}", "-").replace(" ", "-"));
+}
+
+pub fn print_report_without_header(top_changed_files: &[ChangedFileStats]) {
+    top_changed_files.iter().for_each(|changes| {
+        println!("{}", format_changes(changes));
     });
 }
 
 pub fn print_top_complexities_report_without_header(top_complexities: &TopComplexities) {
-    println!(
-        "{}\t{}",
-        top_complexities.code_filename, top_complexities.num_changes
-    );
+    println!("{}", format_changes(&top_complexities.changes));
     top_complexities
         .function_complexities
         .iter()
@@ -24,17 +66,13 @@ pub fn print_top_complexities_report_without_header(top_complexities: &TopComple
         })
 }
 
-pub fn print_heat_map_report(top_changed_files: &ChangedFileCounts) {
-    println!("{}", format!("{:80}", "-").replace(" ", "-"));
-    println!("File\t\tNumber of changes");
-    println!("{}", format!("{:80}", "-").replace(" ", "-"));
+pub fn print_heat_map_report(top_changed_files: &[ChangedFileStats]) {
+    print_header();
     print_report_without_header(top_changed_files);
 }
 
-pub fn print_top_complexities_report(top_changed_files: &Vec<Result<TopComplexities>>) {
-    println!("{}", format!("{:80}", "-").replace(" ", "-"));
-    println!("File\t\tNumber of changes");
-    println!("{}", format!("{:80}", "-").replace(" ", "-"));
+pub fn print_top_complexities_report(top_changed_files: &[Result<TopComplexities>]) {
+    print_header();
     top_changed_files
         .iter()
         .flatten()
@@ -43,3 +81,267 @@ pub fn print_top_complexities_report(top_changed_files: &Vec<Result<TopComplexit
             print_top_complexities_report_without_header(top_complexities);
         });
 }
+
+pub fn print_hotspots_report(hotspots: &[Hotspot]) {
+    println!("{}", format!("{:80}", "-").replace(" ", "-"));
+    println!("{CHANGES_HEADER}\tComplexity\tScore\tQuadrant");
+    println!("{}", format!("{:80}", "-").replace(" ", "-"));
+    hotspots.iter().for_each(|hotspot| {
+        let warning = match hotspot.has_fragmented_ownership() {
+            true => "\tfragmented ownership",
+            false => "",
+        };
+        println!(
+            "{}\t{}\t{:.2}\t{}{}",
+            format_changes(&hotspot.top_complexities.changes),
+            hotspot.complexity,
+            hotspot.score,
+            hotspot.quadrant,
+            warning
+        );
+    });
+}
+
+pub fn print_function_churn_report(function_churn: &[FunctionChurn]) {
+    println!("{}", format!("{:80}", "-").replace(" ", "-"));
+    println!("Function\tFile\tNumber of changes\tCognitive complexity");
+    println!("{}", format!("{:80}", "-").replace(" ", "-"));
+    function_churn.iter().for_each(|churn| {
+        println!(
+            "{}\t{}\t{}\t{}",
+            churn.function, churn.filename, churn.num_changes, churn.cognitive_complexity_value
+        );
+    });
+}
+
+fn csv_field(field: &str) -> String {
+    match field.contains([',', '"', '\n']) {
+        true => format!("\"{}\"", field.replace('"', "\"\"")),
+        false => field.to_string(),
+    }
+}
+
+pub fn print_trend_report(trends: &[FileTrend], format: TrendFormat) -> Result<()> {
+    match format {
+        TrendFormat::Table => {
+            println!("{}", format!("{:80}", "-").replace(" ", "-"));
+            println!("File\t\tDate\tCommit\tTotal complexity\tMax complexity");
+            println!("{}", format!("{:80}", "-").replace(" ", "-"));
+            trends.iter().for_each(|trend| {
+                println!("{}", trend.filename);
+                trend.points.iter().for_each(|point| {
+                    println!(
+                        "\t{}\t{}\t{}\t{}",
+                        point.date.format("%Y-%m-%d"),
+                        point.sha,
+                        point.total_complexity,
+                        point.max_complexity
+                    );
+                });
+            });
+        }
+        TrendFormat::Json => println!("{}", serde_json::to_string_pretty(trends)?),
+        TrendFormat::Csv => {
+            println!("file,date,commit,total_complexity,max_complexity");
+            trends.iter().for_each(|trend| {
+                trend.points.iter().for_each(|point| {
+                    println!(
+                        "{},{},{},{},{}",
+                        csv_field(&trend.filename),
+                        point.date.to_rfc3339(),
+                        point.sha,
+                        point.total_complexity,
+                        point.max_complexity
+                    );
+                });
+            });
+        }
+    }
+    Ok(())
+}
+
+// Failed commits listed in the summary, the rest are only counted
+const MAX_FAILED_COMMITS_LISTED: usize = 10;
+
+/// Tells on stderr, so it doesn't mix with JSON or CSV reports, which commits the report leaves out.
+pub fn print_failed_commits_summary(failed_commits: &[FailedCommit]) {
+    if failed_commits.is_empty() {
+        return;
+    }
+    eprintln!();
+    // We don't know how many commits a failed listing left out
+    if let Some(listing_failure) = failed_commits
+        .iter()
+        .find(|failed_commit| failed_commit.sha.is_none())
+    {
+        eprintln!(
+            "Listing the commits failed, the history is truncated: the report only counts the commits listed before \
+            the error, and may miss most of the analysis window. Error: {}",
+            listing_failure.error
+        );
+    }
+    eprintln!(
+        "{} commits could not be fetched, the report doesn't count them:",
+        failed_commits.len()
+    );
+    failed_commits
+        .iter()
+        .take(MAX_FAILED_COMMITS_LISTED)
+        .for_each(|failed_commit| {
+            eprintln!(
+                "  {}: {}",
+                failed_commit.sha.as_deref().unwrap_or("listing"),
+                failed_commit.error
+            );
+        });
+    if failed_commits.len() > MAX_FAILED_COMMITS_LISTED {
+        eprintln!("  ...");
+    }
+}
+
+// Steps of the progress bar, which tells how much of the analysis window is covered
+const PROGRESS_BAR_LENGTH: u64 = 1000;
+// How often the progress is logged when stderr isn't a terminal
+const PROGRESS_LINE_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Shows how far mining the history got, as a progress bar on a terminal and as a line every few seconds otherwise.
+pub fn show_mining_progress() -> MiningProgress {
+    let describe = |progress: &Progress| {
+        let eta = match progress.eta {
+            Some(eta) => format!(", {} left", HumanDuration(eta)),
+            None => String::new(),
+        };
+        format!(
+            "{} commits listed, {} fetched, {} from the cache, {} errors{eta}",
+            progress.listed, progress.fetched, progress.cache_hits, progress.errors
+        )
+    };
+    if std::io::stderr().is_terminal() {
+        // Every mining pass has a bar of its own
+        let progress_bar: Mutex<Option<ProgressBar>> = Mutex::default();
+        return MiningProgress::new(move |progress| {
+            let mut progress_bar = progress_bar.lock().unwrap();
+            match progress.finished {
+                // Clear the bar before the reports are printed
+                true => {
+                    if let Some(progress_bar) = progress_bar.take() {
+                        progress_bar.finish_and_clear();
+                    }
+                }
+                false => {
+                    let progress_bar = progress_bar.get_or_insert_with(|| {
+                        ProgressBar::new(PROGRESS_BAR_LENGTH).with_style(
+                            ProgressStyle::with_template(
+                                "Mining history [{bar:30}] {percent:>3}% {msg}",
+                            )
+                            .expect("Invalid progress bar template")
+                            .progress_chars("=> "),
+                        )
+                    });
+                    progress_bar.set_position(
+                        (progress.window_covered * PROGRESS_BAR_LENGTH as f64) as u64,
+                    );
+                    progress_bar.set_message(describe(progress));
+                }
+            }
+        });
+    }
+
+    let last_line = Mutex::new(Instant::now());
+    MiningProgress::new(move |progress| {
+        let mut last_line = last_line.lock().unwrap();
+        if last_line.elapsed() >= PROGRESS_LINE_INTERVAL {
+            *last_line = Instant::now();
+            log::info!(
+                "Mining history: {:.0}% of the window, {}",
+                progress.window_covered * 100.0,
+                describe(progress)
+            );
+        }
+    })
+}
+
+pub fn print_coupling_report(coupled_files: &[CoupledFiles]) {
+    println!("{}", format!("{:80}", "-").replace(" ", "-"));
+    println!("File\tCoupled file\tShared commits\tCommits of each\tDegree");
+    println!("{}", format!("{:80}", "-").replace(" ", "-"));
+    coupled_files.iter().for_each(|pair| {
+        println!(
+            "{}\t{}\t{}\t{}/{}\t{:.0}%",
+            pair.filename,
+            pair.coupled_filename,
+            pair.shared_commits,
+            pair.revisions.0,
+            pair.revisions.1,
+            pair.degree * 100.0
+        );
+    });
+}
+
+fn format_knowledge(knowledge: &KnowledgeDistribution) -> String {
+    let warning = match knowledge.main_developer_inactive {
+        true => "\tmain developer inactive",
+        false => "",
+    };
+    format!(
+        "{}\t{}\t{}\t{}\t{}{}",
+        knowledge.path,
+        knowledge.num_changes,
+        knowledge.num_authors,
+        knowledge.num_key_authors,
+        knowledge.main_developer.as_deref().unwrap_or("-"),
+        warning
+    )
+}
+
+pub fn print_defects_report(
+    defect_prone_files: &[(ChangedFileStats, Option<u32>)],
+    complexity_defects_correlation: Option<f64>,
+) {
+    println!("{}", format!("{:80}", "-").replace(" ", "-"));
+    println!("File\t\tNumber of changes\tLines changed\tBug fixes\tFix ratio\tBug fixes per 1000 lines\tComplexity");
+    println!("{}", format!("{:80}", "-").replace(" ", "-"));
+    defect_prone_files.iter().for_each(|(changes, complexity)| {
+        let defect_density = changes
+            .defect_density()
+            .map_or("-".to_string(), |density| format!("{density:.1}"));
+        let complexity = complexity.map_or("-".to_string(), |complexity| complexity.to_string());
+        println!(
+            "{}\t{}\t{}\t{}\t{:.0}%\t{}\t{}",
+            changes.filename,
+            changes.num_commits,
+            changes.lines_changed,
+            changes.num_bug_fixes,
+            changes.bug_fix_ratio() * 100.0,
+            defect_density,
+            complexity
+        );
+    });
+    if let Some(correlation) = complexity_defects_correlation {
+        println!();
+        println!("Correlation between complexity and bug fixes per 1000 lines: {correlation:.2}");
+    }
+}
+
+pub fn print_knowledge_report(
+    hot_files: &[(KnowledgeDistribution, Option<u32>)],
+    directories: &[KnowledgeDistribution],
+) {
+    let key_authors_header = format!("Authors with {:.0}% of changes", KEY_AUTHORS_SHARE * 100.0);
+    println!("{}", format!("{:80}", "-").replace(" ", "-"));
+    println!(
+        "File\t\tNumber of changes\tAuthors\t{key_authors_header}\tMain developer\tComplexity"
+    );
+    println!("{}", format!("{:80}", "-").replace(" ", "-"));
+    hot_files.iter().for_each(|(knowledge, complexity)| {
+        let complexity = complexity.map_or("-".to_string(), |complexity| complexity.to_string());
+        println!("{}\t{}", format_knowledge(knowledge), complexity);
+    });
+    println!();
+    println!("{}", format!("{:80}", "-").replace(" ", "-"));
+    println!("Directory\tNumber of changes\tAuthors\t{key_authors_header}\tMain developer");
+    println!("{}", format!("{:80}", "-").replace(" ", "-"));
+    directories.iter().for_each(|knowledge| {
+        println!("{}", format_knowledge(knowledge));
+    });
+}
@@ -0,0 +1,272 @@
+use crate::complexity::{compute_cognitive_index_of_code, ProgrammingLang};
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use clap::ValueEnum;
+use complexity_radar::history::{ChangeHistoryProvider, HistoryQuery, HistoryStore, RenameTracker};
+use futures_util::StreamExt;
+use serde::Serialize;
+use std::collections::HashMap;
+
+/// How to print the complexity trend.
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TrendFormat {
+    #[default]
+    Table,
+    Json,
+    Csv,
+}
+
+/// Complexity of a file at one of the sampled points of the analysis window.
+#[derive(Serialize, Debug, PartialEq)]
+pub struct TrendPoint {
+    pub date: DateTime<Utc>,
+    /// Last commit before the date
+    pub sha: String,
+    pub total_complexity: u32,
+    pub max_complexity: u32,
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+pub struct FileTrend {
+    pub filename: String,
+    pub points: Vec<TrendPoint>,
+}
+
+// Names of the complexity metrics of a file at a revision, in the history store
+const TOTAL_COMPLEXITY_METRIC: &str = "total_complexity";
+const MAX_COMPLEXITY_METRIC: &str = "max_complexity";
+
+/// `num_points` dates evenly spread from `since` to `until`, both included.
+pub fn sample_dates(
+    since: DateTime<Utc>,
+    until: DateTime<Utc>,
+    num_points: usize,
+) -> Vec<DateTime<Utc>> {
+    match num_points {
+        0 => vec![],
+        1 => vec![until],
+        _ => {
+            let step = (until - since) / (num_points as i32 - 1);
+            (0..num_points)
+                .map(|point| since + step * point as i32)
+                .collect()
+        }
+    }
+}
+
+/// Total and highest function complexity of a file at a revision, `None` if it didn't exist or can't be parsed.
+/// Complexities recorded in the `store` aren't computed again, and the computed ones are recorded.
+async fn complexity_at(
+    history: &dyn ChangeHistoryProvider,
+    store: Option<&HistoryStore>,
+    filename: &str,
+    sha: &str,
+) -> Option<(u32, u32)> {
+    if let Some(store) = store {
+        if let (Ok(Some(total_complexity)), Ok(Some(max_complexity))) = (
+            store.metric(sha, filename, TOTAL_COMPLEXITY_METRIC),
+            store.metric(sha, filename, MAX_COMPLEXITY_METRIC),
+        ) {
+            return Some((total_complexity as u32, max_complexity as u32));
+        }
+    }
+
+    let code = history.file_contents(filename, Some(sha)).await.ok()?;
+    let function_complexities =
+        compute_cognitive_index_of_code(ProgrammingLang::Rust, filename.as_ref(), &code).ok()?;
+    let complexities = function_complexities
+        .iter()
+        .map(|function_complexity| function_complexity.cognitive_complexity_value as u32);
+    let (total_complexity, max_complexity): (u32, u32) =
+        (complexities.clone().sum(), complexities.max().unwrap_or(0));
+
+    if let Some(store) = store {
+        let recorded = store
+            .record_metric(
+                sha,
+                filename,
+                TOTAL_COMPLEXITY_METRIC,
+                total_complexity.into(),
+            )
+            .and_then(|_| {
+                store.record_metric(sha, filename, MAX_COMPLEXITY_METRIC, max_complexity.into())
+            });
+        // It will only have to be computed again next time
+        if let Err(error) = recorded {
+            log::warn!("Couldn't record the complexity of {filename} at {sha}: {error:#}");
+        }
+    }
+    Some((total_complexity, max_complexity))
+}
+
+/// Paths the files had at every sampled commit, following the renames of the analysis window back from their
+/// current paths. Commits older than the window have the paths from before every rename in it.
+async fn paths_at_samples(
+    history: &dyn ChangeHistoryProvider,
+    query: &HistoryQuery,
+    filenames: &[String],
+    samples: &[(DateTime<Utc>, String)],
+) -> Result<HashMap<String, Vec<String>>> {
+    let paths_before = |rename_tracker: &RenameTracker| -> Vec<String> {
+        filenames
+            .iter()
+            .map(|filename| rename_tracker.path_before(filename).to_string())
+            .collect()
+    };
+    let mut rename_tracker = RenameTracker::default();
+    let mut paths = HashMap::new();
+    let mut commits = history.commits(query).await?;
+    while let Some(commit) = commits.next().await {
+        let commit = match commit {
+            Ok(commit) => commit,
+            Err(error) => {
+                query.failed_commits.record(&error);
+                continue;
+            }
+        };
+        // The files are at their paths from after the commit, so before following its renames
+        if samples.iter().any(|(_, sha)| *sha == commit.sha) {
+            paths.insert(commit.sha.clone(), paths_before(&rename_tracker));
+        }
+        rename_tracker.follow(commit);
+    }
+    for (_, sha) in samples {
+        paths
+            .entry(sha.clone())
+            .or_insert_with(|| paths_before(&rename_tracker));
+    }
+    Ok(paths)
+}
+
+/// Computes the complexity of every file at `num_points` points of the analysis window, reading it by the path it
+/// had at each point. Points where a file didn't exist yet, or can't be parsed, are left out of its trend.
+pub async fn compute_complexity_trend(
+    history: &dyn ChangeHistoryProvider,
+    store: Option<&HistoryStore>,
+    query: &HistoryQuery,
+    filenames: &[String],
+    num_points: usize,
+) -> Result<Vec<FileTrend>> {
+    let until = query.until.unwrap_or_else(Utc::now);
+    let mut samples = Vec::new();
+    for date in sample_dates(query.since, until, num_points) {
+        if let Some(sha) = history
+            .last_commit_before(query.git_ref.as_deref(), date)
+            .await?
+        {
+            samples.push((date, sha));
+        }
+    }
+
+    let paths = paths_at_samples(history, query, filenames, &samples).await?;
+
+    let mut trends = Vec::new();
+    for (file, filename) in filenames.iter().enumerate() {
+        let mut points = Vec::new();
+        for (date, sha) in &samples {
+            let Some((total_complexity, max_complexity)) =
+                complexity_at(history, store, &paths[sha][file], sha).await
+            else {
+                continue;
+            };
+            points.push(TrendPoint {
+                date: *date,
+                sha: sha.clone(),
+                total_complexity,
+                max_complexity,
+            });
+        }
+        trends.push(FileTrend {
+            filename: filename.clone(),
+            points,
+        });
+    }
+    Ok(trends)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use chrono::TimeZone;
+    use complexity_radar::history::LocalGitHistory;
+    use std::path::Path;
+
+    fn git(repo_path: &Path, args: &[&str], date: &str) {
+        let status = std::process::Command::new("git")
+            .arg("-C")
+            .arg(repo_path)
+            .args([
+                "-c",
+                "user.name=Radar",
+                "-c",
+                "user.email=radar@example.com",
+            ])
+            .args(args)
+            .env("GIT_AUTHOR_DATE", date)
+            .env("GIT_COMMITTER_DATE", date)
+            .status()
+            .expect("git is required to run this test");
+        assert!(status.success(), "git {args:?} failed");
+    }
+
+    fn commit_code(repo_path: &Path, filename: &str, code: &str, date: &str) {
+        std::fs::write(repo_path.join(filename), code).unwrap();
+        git(repo_path, &["add", filename], date);
+        git(repo_path, &["commit", "-q", "-m", filename], date);
+    }
+
+    #[test]
+    fn sample_dates_evenly_including_both_ends() {
+        let since = Utc.with_ymd_and_hms(2023, 1, 1, 0, 0, 0).unwrap();
+        let until = Utc.with_ymd_and_hms(2023, 1, 31, 0, 0, 0).unwrap();
+
+        assert_eq!(
+            vec![
+                since,
+                Utc.with_ymd_and_hms(2023, 1, 11, 0, 0, 0).unwrap(),
+                Utc.with_ymd_and_hms(2023, 1, 21, 0, 0, 0).unwrap(),
+                until,
+            ],
+            sample_dates(since, until, 4)
+        );
+        assert_eq!(vec![until], sample_dates(since, until, 1));
+    }
+
+    #[tokio::test]
+    async fn follow_a_renamed_file_back_to_its_previous_path() {
+        let repo = tempfile::tempdir().unwrap();
+        git(repo.path(), &["init", "-q"], "2023-01-01T00:00:00Z");
+        let simple = "fn parse(a: bool) {\n    if a {}\n}\n";
+        let nested = "fn parse(a: bool) {\n    if a {\n        if a {}\n    }\n}\n";
+        commit_code(repo.path(), "lib.rs", simple, "2023-01-01T00:00:00Z");
+        std::fs::create_dir(repo.path().join("src")).unwrap();
+        git(
+            repo.path(),
+            &["mv", "lib.rs", "src/radar.rs"],
+            "2023-02-01T00:00:00Z",
+        );
+        git(
+            repo.path(),
+            &["commit", "-q", "-m", "Move"],
+            "2023-02-01T00:00:00Z",
+        );
+        commit_code(repo.path(), "src/radar.rs", nested, "2023-03-01T00:00:00Z");
+        let local_history = LocalGitHistory::new(repo.path().into());
+        let query = HistoryQuery::new(
+            Utc.with_ymd_and_hms(2023, 1, 15, 0, 0, 0).unwrap(),
+            Some(Utc.with_ymd_and_hms(2023, 3, 15, 0, 0, 0).unwrap()),
+        );
+
+        let trends =
+            compute_complexity_trend(&local_history, None, &query, &["src/radar.rs".into()], 3)
+                .await
+                .unwrap();
+
+        let complexities: Vec<_> = trends[0]
+            .points
+            .iter()
+            .map(|point| point.total_complexity)
+            .collect();
+        assert_eq!(vec![1, 1, 3], complexities);
+    }
+}